@@ -0,0 +1,119 @@
+// moves per second on fixed seeds and board sizes: `cargo bench`, or `cargo bench -- rules` for one group.
+// board_shift goes through the game systems a frame per key press, like playing the game.
+// rules is the pure core on its own: slide_tiles on a board from the middle of a game, and
+// whole steps of the gym environment with the new tile and the game over check.
+// there is no search based player in the game to measure, bots are separate programs (src/bot.rs)
+use bevy::prelude::*;
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use boxes::gym::{Env, EnvConfig, ACTIONS};
+use boxes::{headless_app, slide_tiles, Board, BoardShift, Position, RunState, Settings, Variant};
+
+const SEED: u64 = 2048;
+const SIZES: [u8; 3] = [4, 6, 8];
+// the same four directions over and over last longer than this on every size with SEED,
+// so every move of a batch is made in a game that is still going
+const MOVES: usize = 100;
+const CYCLE: [BoardShift; 4] = [BoardShift::Left, BoardShift::Up, BoardShift::Right, BoardShift::Down];
+
+fn config(size: u8) -> EnvConfig {
+    EnvConfig { width: size, height: size, variant: Variant::Classic, ..default() }
+}
+
+fn key(shift: BoardShift) -> KeyCode {
+    match shift {
+        BoardShift::Left => KeyCode::Left,
+        BoardShift::Right => KeyCode::Right,
+        BoardShift::Up => KeyCode::Up,
+        BoardShift::Down => KeyCode::Down,
+    }
+}
+
+// a new game on a size x size board, the first two tiles dealt
+fn playing_app(size: u8) -> App {
+    let mut app = headless_app(SEED);
+    app.update();
+    let mut settings = app.world.resource_mut::<Settings>();
+    settings.board_width = size;
+    settings.board_height = size;
+    app.world.resource_mut::<NextState<RunState>>().set(RunState::Playing);
+    app.update();
+    app
+}
+
+fn board_shift(c: &mut Criterion) {
+    let mut group = c.benchmark_group("board_shift");
+    group.throughput(Throughput::Elements(MOVES as u64));
+    for size in SIZES {
+        group.bench_with_input(BenchmarkId::new("frames", format!("{size}x{size}")), &size, |b, &size| {
+            b.iter_batched(
+                || playing_app(size),
+                |mut app| {
+                    for shift in CYCLE.iter().cycle().take(MOVES) {
+                        app.world.resource_mut::<Input<KeyCode>>().press(key(*shift));
+                        app.update();
+                        let mut input = app.world.resource_mut::<Input<KeyCode>>();
+                        input.release(key(*shift));
+                        input.clear();
+                    }
+                    app
+                },
+                BatchSize::LargeInput,
+            );
+        });
+    }
+    group.finish();
+}
+
+// the tiles of a game MOVES / 2 moves in, as (position, value) with y = 0 the bottom row
+fn midgame_tiles(size: u8) -> Vec<(Position, u32)> {
+    let mut env = Env::new(&config(size));
+    env.reset(SEED);
+    for shift in CYCLE.iter().cycle().take(MOVES / 2) {
+        env.step(*shift);
+    }
+    let observation = env.observation();
+    (0..size).rev()
+        .flat_map(|y| (0..size).map(move |x| Position { x, y }))
+        .zip(observation.cells)
+        .filter(|(_, value)| *value > 0)
+        .collect()
+}
+
+fn rules(c: &mut Criterion) {
+    let mut group = c.benchmark_group("rules");
+    for size in SIZES {
+        let board = Board::new(size, size);
+        let tiles = midgame_tiles(size);
+        group.throughput(Throughput::Elements(ACTIONS.len() as u64));
+        group.bench_with_input(BenchmarkId::new("slide_tiles", format!("{size}x{size}")), &tiles, |b, tiles| {
+            b.iter(|| {
+                for shift in ACTIONS {
+                    let mut tiles = tiles.clone();
+                    black_box(slide_tiles(&board, &[], Variant::Classic, shift, &mut tiles));
+                }
+            });
+        });
+
+        group.throughput(Throughput::Elements(MOVES as u64));
+        group.bench_with_input(BenchmarkId::new("env_step", format!("{size}x{size}")), &size, |b, &size| {
+            b.iter_batched(
+                || {
+                    let mut env = Env::new(&config(size));
+                    env.reset(SEED);
+                    env
+                },
+                |mut env| {
+                    for shift in CYCLE.iter().cycle().take(MOVES) {
+                        black_box(env.step(*shift));
+                    }
+                    env
+                },
+                BatchSize::SmallInput,
+            );
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, board_shift, rules);
+criterion_main!(benches);
@@ -0,0 +1,307 @@
+use std::time::Duration;
+use bevy::asset::{AssetLoader, LoadContext, LoadedAsset};
+use bevy::audio::{AddAudioSource, AudioSink, Source};
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::utils::BoxedFuture;
+use serde::Deserialize;
+use crate::{MoveEvent, RunState, ScoreGainEvent, SpawnedEvent};
+use crate::leaderboard::PendingEntry;
+use crate::settings::{load_settings, Settings, SETTINGS_FILE};
+use crate::storage::Storage;
+
+const SAMPLE_RATE: u32 = 44_100;
+// fade in over a few milliseconds so notes don't click
+const ATTACK: f32 = 0.005;
+
+
+// needs the asset and audio plugins, so like the ui it stays out of the tests
+pub struct GameAudioPlugin;
+
+impl Plugin for GameAudioPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_audio_source::<Tone>()
+            .init_asset_loader::<ToneLoader>()
+            .init_resource::<Sounds>()
+            .init_resource::<Music>()
+            .add_startup_system(start_music.after(load_settings))
+            .add_system(toggle_mute)
+            .add_system(music_volume.run_if(resource_changed::<Settings>()))
+            .add_system(play_move_sounds)
+            .add_system(play_sound(|sounds| &sounds.win).in_schedule(OnEnter(RunState::Won)))
+            .add_system(play_sound(|sounds| &sounds.game_over).in_schedule(OnEnter(RunState::GameOver)));
+    }
+}
+
+// a sound is a few notes in a json file under assets/sounds, synthesized as it plays
+#[derive(Deserialize, TypeUuid, Debug, Clone)]
+#[uuid = "6b3c1a52-8f0e-4d7a-9a61-2f5e0c4b7d13"]
+pub struct Tone {
+    wave: Wave,
+    gain: f32,
+    notes: Vec<Note>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum Wave {
+    Sine,
+    Square,
+    Triangle,
+    Noise,  // ignores the frequency, but a 0 is still a rest
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+struct Note {
+    frequency: f32,  // 0 is a rest
+    seconds: f32,
+}
+
+impl Note {
+    fn samples(&self) -> u32 {
+        (self.seconds * SAMPLE_RATE as f32) as u32
+    }
+}
+
+impl Tone {
+    fn duration(&self) -> Duration {
+        Duration::from_secs_f32(self.notes.iter().map(|note| note.seconds).sum())
+    }
+}
+
+impl Decodable for Tone {
+    type DecoderItem = f32;
+    type Decoder = ToneDecoder;
+
+    fn decoder(&self) -> ToneDecoder {
+        ToneDecoder { tone: self.clone(), note: 0, sample: 0, noise: 0x2545_f491 }
+    }
+}
+
+pub struct ToneDecoder {
+    tone: Tone,
+    note: usize,
+    sample: u32,
+    noise: u32,  // xorshift state
+}
+
+impl Iterator for ToneDecoder {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let note = loop {
+            let note = self.tone.notes.get(self.note)?;
+            if self.sample < note.samples() { break *note; }
+            self.note += 1;
+            self.sample = 0;
+        };
+        let t = self.sample as f32 / SAMPLE_RATE as f32;
+        self.sample += 1;
+        if note.frequency <= 0.0 { return Some(0.0); }
+
+        let phase = (t * note.frequency).fract();
+        let wave = match self.tone.wave {
+            Wave::Sine => (phase * std::f32::consts::TAU).sin(),
+            Wave::Square => if phase < 0.5 { 1.0 } else { -1.0 },
+            Wave::Triangle => 4.0 * (phase - 0.5).abs() - 1.0,
+            Wave::Noise => {
+                self.noise ^= self.noise << 13;
+                self.noise ^= self.noise >> 17;
+                self.noise ^= self.noise << 5;
+                self.noise as f32 / u32::MAX as f32 * 2.0 - 1.0
+            }
+        };
+        // quick fade in, then fade out over the rest of the note
+        let envelope = (t / ATTACK).min(1.0) * (1.0 - t / note.seconds);
+        Some(wave * envelope * self.tone.gain)
+    }
+}
+
+impl Source for ToneDecoder {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        Some(self.tone.duration())
+    }
+}
+
+#[derive(Default)]
+struct ToneLoader;
+
+impl AssetLoader for ToneLoader {
+    fn load<'a>(&'a self, bytes: &'a [u8], load_context: &'a mut LoadContext) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let tone: Tone = serde_json::from_slice(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(tone));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["tone"]
+    }
+}
+
+#[derive(Resource)]
+struct Sounds {
+    slide: Handle<Tone>,
+    merge: Handle<Tone>,
+    spawn: Handle<Tone>,
+    blocked: Handle<Tone>,
+    win: Handle<Tone>,
+    game_over: Handle<Tone>,
+    music: Handle<Tone>,
+}
+
+impl FromWorld for Sounds {
+    fn from_world(world: &mut World) -> Self {
+        let asset_server = world.resource::<AssetServer>();
+        Sounds {
+            slide: asset_server.load("sounds/slide.tone"),
+            merge: asset_server.load("sounds/merge.tone"),
+            spawn: asset_server.load("sounds/spawn.tone"),
+            blocked: asset_server.load("sounds/blocked.tone"),
+            win: asset_server.load("sounds/win.tone"),
+            game_over: asset_server.load("sounds/game_over.tone"),
+            music: asset_server.load("sounds/music.tone"),
+        }
+    }
+}
+
+// the looping background music, kept to follow volume changes
+#[derive(Resource, Default)]
+struct Music(Option<Handle<AudioSink>>);
+
+fn start_music(
+    audio: Res<Audio<Tone>>,
+    sounds: Res<Sounds>,
+    sinks: Res<Assets<AudioSink>>,
+    settings: Res<Settings>,
+    mut music: ResMut<Music>,
+) {
+    let sink = audio.play_with_settings(sounds.music.clone(), PlaybackSettings::LOOP.with_volume(settings.music_level()));
+    music.0 = Some(sinks.get_handle(sink));
+}
+
+fn music_volume(music: Res<Music>, sinks: Res<Assets<AudioSink>>, settings: Res<Settings>) {
+    if let Some(sink) = music.0.as_ref().and_then(|handle| sinks.get(handle)) {
+        sink.set_volume(settings.music_level());
+    }
+}
+
+// not while a name is typed for the leaderboard, the key is a letter there
+fn toggle_mute(keyboard_input: Res<Input<KeyCode>>, mut settings: ResMut<Settings>, storage: Res<Storage>, pending: Res<PendingEntry>) {
+    if pending.0.is_none() && keyboard_input.just_pressed(settings.key_bindings.mute) {
+        settings.muted = !settings.muted;
+        storage.save(SETTINGS_FILE, &*settings);
+    }
+}
+
+// bigger merges sound higher, a 4 plays the sound as is
+fn merge_pitch(value: u32) -> f32 {
+    1.0 + 0.08 * value.max(4).ilog2().saturating_sub(2) as f32
+}
+
+fn play_move_sounds(
+    mut move_reader: EventReader<MoveEvent>,
+    mut gain_reader: EventReader<ScoreGainEvent>,
+    mut spawned_reader: EventReader<SpawnedEvent>,
+    audio: Res<Audio<Tone>>,
+    sounds: Res<Sounds>,
+    settings: Res<Settings>,
+) {
+    let volume = settings.effects_level();
+    let effect = PlaybackSettings::ONCE.with_volume(volume);
+    for event in move_reader.iter() {
+        let sound = match event {
+            MoveEvent::Slid { .. } => &sounds.slide,
+            MoveEvent::Blocked { .. } => &sounds.blocked,
+        };
+        if volume > 0.0 { audio.play_with_settings(sound.clone(), effect); }
+    }
+    // one merge sound a move, pitched by the biggest tile it made
+    for gain in gain_reader.iter() {
+        let Some(biggest) = gain.merges.iter().map(|(_, value)| *value).max() else { continue; };
+        if volume > 0.0 { audio.play_with_settings(sounds.merge.clone(), effect.with_speed(merge_pitch(biggest))); }
+    }
+    for _event in spawned_reader.iter() {
+        if volume > 0.0 { audio.play_with_settings(sounds.spawn.clone(), effect); }
+    }
+}
+
+fn play_sound(sound: fn(&Sounds) -> &Handle<Tone>) -> impl Fn(Res<Audio<Tone>>, Res<Sounds>, Res<Settings>) {
+    move |audio, sounds, settings| {
+        let volume = settings.effects_level();
+        if volume > 0.0 {
+            audio.play_with_settings(sound(&sounds).clone(), PlaybackSettings::ONCE.with_volume(volume));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::leaderboard::LeaderboardEntry;
+
+    #[test]
+    fn sounds_in_assets_decode_to_their_length() {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("assets/sounds");
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            let tone: Tone = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+            let samples: Vec<f32> = tone.decoder().collect();
+
+            let expected: u32 = tone.notes.iter().map(Note::samples).sum();
+            assert_eq!(samples.len(), expected as usize, "{path:?}");
+            assert!(samples.iter().all(|sample| sample.abs() <= tone.gain), "{path:?} clips");
+        }
+    }
+
+    #[test]
+    fn mute_key_is_a_letter_while_typing_a_name() {
+        let mut app = App::new();
+        app.init_resource::<Input<KeyCode>>()
+            .init_resource::<Settings>()
+            .init_resource::<PendingEntry>()
+            .insert_resource(Storage::in_memory())
+            .add_system(toggle_mute);
+        let press_mute = |app: &mut App| {
+            app.world.resource_mut::<Input<KeyCode>>().press(KeyCode::M);
+            app.update();
+            app.world.resource_mut::<Input<KeyCode>>().clear();
+        };
+
+        press_mute(&mut app);
+        assert!(app.world.resource::<Settings>().muted);
+        app.world.resource_mut::<PendingEntry>().0 = Some(LeaderboardEntry {
+            name: String::new(),
+            score: 4,
+            max_tile: 4,
+            board_width: 4,
+            board_height: 4,
+            variant: "Classic".to_string(),
+            moves: 1,
+            duration: Duration::ZERO,
+            date: String::new(),
+        });
+        press_mute(&mut app);
+        assert!(app.world.resource::<Settings>().muted, "still muted");
+    }
+
+    #[test]
+    fn bigger_merges_sound_higher() {
+        assert_eq!(merge_pitch(4), 1.0);
+        assert!(merge_pitch(8) > merge_pitch(4));
+        assert!(merge_pitch(2048) > merge_pitch(1024));
+    }
+}
@@ -0,0 +1,127 @@
+// pairs up players for the online versus and passes their boards between them.
+// cargo run --bin server -- [address] [--garbage]
+// the address defaults to 127.0.0.1:2048, --garbage has big merges send blockers to the other board
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use boxes::protocol::{Message, DEFAULT_ADDRESS};
+
+fn main() -> io::Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let garbage = args.iter().any(|arg| arg == "--garbage");
+    let address = args.iter().find(|arg| !arg.starts_with("--")).map_or(DEFAULT_ADDRESS, String::as_str);
+
+    let listener = TcpListener::bind(address)?;
+    println!("waiting for players on {address}");
+    serve(listener, garbage);
+    Ok(())
+}
+
+// players are paired in the order they connect, every pair plays on threads of its own.
+// someone who gave up waiting is passed over for the next one to come
+fn serve(listener: TcpListener, garbage: bool) {
+    let mut waiting: Option<TcpStream> = None;
+    for stream in listener.incoming().map_while(Result::ok) {
+        let Some(first) = waiting.take().filter(still_connected) else {
+            waiting = Some(stream);
+            continue;
+        };
+        thread::spawn(move || {
+            if let Err(error) = play(first, stream, new_seed(), garbage) {
+                println!("game ended: {error}");
+            }
+        });
+    }
+}
+
+// a waiting player has nothing to say before the start, so anything but nothing to read means they're gone
+fn still_connected(stream: &TcpStream) -> bool {
+    if stream.set_nonblocking(true).is_err() { return false; }
+    let alive = matches!(stream.peek(&mut [0]), Err(error) if error.kind() == io::ErrorKind::WouldBlock);
+    stream.set_nonblocking(false).is_ok() && alive
+}
+
+fn new_seed() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_nanos() as u64)
+}
+
+fn play(first: TcpStream, second: TcpStream, seed: u64, garbage: bool) -> io::Result<()> {
+    let start = Message::Start { seed, garbage }.to_line();
+    (&first).write_all(start.as_bytes())?;
+    (&second).write_all(start.as_bytes())?;
+
+    let there = thread::spawn({
+        let (from, to) = (first.try_clone()?, second.try_clone()?);
+        move || relay(from, to)
+    });
+    relay(second, first);
+    let _ = there.join();
+    Ok(())
+}
+
+// what a player sends goes to the other one as it is, only the server starts games and says who left
+fn relay(from: TcpStream, to: TcpStream) {
+    for line in BufReader::new(&from).lines().map_while(Result::ok) {
+        match Message::from_line(&line) {
+            Some(Message::Start { .. } | Message::Left) | None => continue,
+            Some(message) => {
+                if (&to).write_all(message.to_line().as_bytes()).is_err() { break; }
+            }
+        }
+    }
+    let _ = (&to).write_all(Message::Left.to_line().as_bytes());
+    let _ = to.shutdown(Shutdown::Both);
+    let _ = from.shutdown(Shutdown::Both);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(reader: &mut BufReader<TcpStream>) -> Option<Message> {
+        let mut line = String::new();
+        reader.read_line(&mut line).ok()?;
+        Message::from_line(&line)
+    }
+
+    #[test]
+    fn pairs_players_and_passes_boards_between_them() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || serve(listener, true));
+
+        let one = TcpStream::connect(address).unwrap();
+        let two = TcpStream::connect(address).unwrap();
+        let (mut one_reader, mut two_reader) = (BufReader::new(one.try_clone().unwrap()), BufReader::new(two.try_clone().unwrap()));
+        let Some(Message::Start { seed, garbage: true }) = read(&mut one_reader) else { panic!("the first player gets a start") };
+        assert_eq!(read(&mut two_reader), Some(Message::Start { seed, garbage: true }), "both deal from the same seed");
+
+        let board = Message::Board { tiles: vec![(0, 0, 4)], walls: vec![], score: 4, moves: 1 };
+        (&one).write_all(board.to_line().as_bytes()).unwrap();
+        (&one).write_all(b"{\"type\":\"left\"}\n").unwrap();  // only the server says who left
+        (&one).write_all(Message::Garbage { count: 1 }.to_line().as_bytes()).unwrap();
+        assert_eq!(read(&mut two_reader), Some(board));
+        assert_eq!(read(&mut two_reader), Some(Message::Garbage { count: 1 }));
+
+        drop(one_reader);
+        one.shutdown(Shutdown::Both).unwrap();
+        assert_eq!(read(&mut two_reader), Some(Message::Left));
+    }
+
+    #[test]
+    fn passes_over_a_player_who_left_while_waiting() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || serve(listener, false));
+
+        drop(TcpStream::connect(address).unwrap());
+        let two = TcpStream::connect(address).unwrap();
+        let three = TcpStream::connect(address).unwrap();
+        three.set_read_timeout(Some(std::time::Duration::from_secs(5))).unwrap();
+        let Some(Message::Start { seed, .. }) = read(&mut BufReader::new(two)) else { panic!("the second player gets a start") };
+        assert_eq!(read(&mut BufReader::new(three)), Some(Message::Start { seed, garbage: false }), "with the third, not the one who left");
+    }
+}
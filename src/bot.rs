@@ -0,0 +1,371 @@
+// bots in any language: a program that reads a board a line on stdin and answers with a direction on stdout.
+// `boxes --bot "python3 my_bot.py"` lets one play on screen, `boxes --tournament 20 "./a" "./b"` has
+// several play the same seeds headless and prints how they did.
+//
+// every turn the game writes "width height" and then every cell row by row from the top left:
+// the value of its tile, 0 when it is empty or # for a wall or a dead cell, all space separated
+//     4 4 2 0 0 0 0 4 0 0 0 0 0 0 0 0 2 0
+// the bot answers a line starting with L, R, U or D for left, right, up or down. no answer within
+// BOT_TIMEOUT, anything else, or a direction that slides nothing ends its game
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::Mutex;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+use bevy::prelude::*;
+use itertools::Itertools;
+use crate::{Board, BoardIndex, BoardShift, Controls, MoveEvent, Points, Position, Progress, ShiftEvent, Wall};
+use crate::gym::{Env, EnvConfig, Observation};
+
+pub const BOT_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BotError {
+    TimedOut,
+    Quit,  // the program exited, or closed its output
+    BadAnswer(String),
+    Stuck,  // its direction didn't slide anything
+}
+
+impl BotError {
+    pub fn describe(&self) -> String {
+        match self {
+            BotError::TimedOut => "timed out".to_string(),
+            BotError::Quit => "quit".to_string(),
+            BotError::BadAnswer(answer) => format!("answered {answer:?}"),
+            BotError::Stuck => "stuck".to_string(),
+        }
+    }
+}
+
+pub struct Bot {
+    pub name: String,
+    command: String,
+    child: Child,
+    stdin: ChildStdin,
+    answers: Mutex<Receiver<String>>,  // its output a line at a time, read by a thread of its own
+}
+
+impl Bot {
+    // the program and its arguments in one string, split on whitespace
+    pub fn spawn(command: &str) -> io::Result<Bot> {
+        let mut parts = command.split_whitespace();
+        let program = parts.next().ok_or(io::ErrorKind::InvalidInput)?;
+        let mut child = Command::new(program)
+            .args(parts)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let (sender, answers) = channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                if sender.send(line).is_err() { break; }
+            }
+        });
+        Ok(Bot { name: bot_name(command), command: command.to_string(), child, stdin, answers: Mutex::new(answers) })
+    }
+
+    // the same program again, after its last game ended in a way that leaves it out of step
+    pub fn restart(&self) -> io::Result<Bot> {
+        Bot::spawn(&self.command)
+    }
+
+    pub fn ask(&mut self, observation: &Observation) -> Result<(), BotError> {
+        writeln!(self.stdin, "{}", board_line(observation)).and_then(|_| self.stdin.flush()).map_err(|_| BotError::Quit)
+    }
+
+    // the answer if it is in, without waiting
+    pub fn poll(&self) -> Result<Option<BoardShift>, BotError> {
+        match self.answers.lock().expect("the reader thread never locks the answers").try_recv() {
+            Ok(answer) => parse_answer(&answer).map(Some),
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(TryRecvError::Disconnected) => Err(BotError::Quit),
+        }
+    }
+
+    pub fn answer(&mut self, observation: &Observation, timeout: Duration) -> Result<BoardShift, BotError> {
+        self.ask(observation)?;
+        match self.answers.lock().expect("the reader thread never locks the answers").recv_timeout(timeout) {
+            Ok(answer) => parse_answer(&answer),
+            Err(RecvTimeoutError::Timeout) => Err(BotError::TimedOut),
+            Err(RecvTimeoutError::Disconnected) => Err(BotError::Quit),
+        }
+    }
+}
+
+impl Drop for Bot {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+// programs that run a bot written for them rather than being the bot
+const INTERPRETERS: [&str; 13] = ["sh", "bash", "python", "pypy", "node", "deno", "bun", "ruby", "perl", "php", "lua", "java", "julia"];
+
+// the file name of the program, or of the script for "python3 my_bot.py", leaving out options like
+// --fast and whatever they take. "python3.11" and "python3" are both python
+fn bot_name(command: &str) -> String {
+    let stem = |part: &str| std::path::Path::new(part).file_stem().map_or(part.to_string(), |stem| stem.to_string_lossy().to_string());
+    let mut parts = command.split_whitespace();
+    let Some(program) = parts.next() else { return String::new(); };
+    let file = std::path::Path::new(program).file_name().map_or(program.to_string(), |name| name.to_string_lossy().to_string());
+    if !INTERPRETERS.contains(&file.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.')) { return stem(program); }
+    parts.find(|part| !part.starts_with('-')).map_or(stem(program), stem)
+}
+
+pub fn board_line(observation: &Observation) -> String {
+    let cells = observation.cells.iter().zip(&observation.blocked)
+        .map(|(value, blocked)| if *blocked { "#".to_string() } else { value.to_string() });
+    format!("{} {} {}", observation.width, observation.height, cells.format(" "))
+}
+
+fn parse_answer(answer: &str) -> Result<BoardShift, BotError> {
+    match answer.trim_start().chars().next().map(|first| first.to_ascii_uppercase()) {
+        Some('L') => Ok(BoardShift::Left),
+        Some('R') => Ok(BoardShift::Right),
+        Some('U') => Ok(BoardShift::Up),
+        Some('D') => Ok(BoardShift::Down),
+        _ => Err(BotError::BadAnswer(answer.to_string())),
+    }
+}
+
+// the bot playing on screen, its moves go to the boards of the key bindings as if the keys were pressed
+#[derive(Resource)]
+pub struct BotPlayer {
+    pub bot: Bot,
+    asked: Option<Instant>,  // when it was last shown the board
+    waiting: bool,
+    pub stopped: Option<BotError>,
+}
+
+impl BotPlayer {
+    pub fn new(bot: Bot) -> Self {
+        BotPlayer { bot, asked: None, waiting: false, stopped: None }
+    }
+
+    pub fn label(&self) -> String {
+        match &self.stopped {
+            Some(error) => format!("Bot: {} ({})", self.bot.name, error.describe()),
+            None => format!("Bot: {}", self.bot.name),
+        }
+    }
+}
+
+// one turn a frame at most: show the board, then look for the answer on the frames after.
+// it sees the first board of the key bindings and its answers go to all of them
+pub fn bot_turn(
+    mut player: ResMut<BotPlayer>,
+    boards: Query<(Entity, &Board, &Controls, &BoardIndex, &Progress, &Children)>,
+    tiles: Query<(&Position, &Points)>,
+    walls: Query<&Position, With<Wall>>,
+    mut shift_writer: EventWriter<ShiftEvent>,
+    mut move_reader: EventReader<MoveEvent>,
+) {
+    let shown = boards.iter()
+        .filter(|(_, _, controls, _, _, _)| **controls == Controls::Bindings)
+        .min_by_key(|(_, _, _, index, _, _)| **index);
+    // the last answer went nowhere on the board it was shown, the others playing along don't count
+    let blocked = move_reader.iter()
+        .any(|event| matches!(event, MoveEvent::Blocked { board } if shown.is_some_and(|(entity, ..)| entity == *board)));
+    if player.stopped.is_some() { return; }
+    let Some((_, board, _, _, progress, children)) = shown else { return; };
+
+    if player.waiting {
+        let timed_out = player.asked.is_some_and(|when| when.elapsed() > BOT_TIMEOUT);
+        match player.bot.poll() {
+            Ok(Some(shift)) => {
+                player.waiting = false;
+                for (entity, _, controls, _, _, _) in boards.iter() {
+                    if *controls == Controls::Bindings { shift_writer.send(ShiftEvent { board: entity, shift }); }
+                }
+            }
+            Ok(None) if timed_out => player.stopped = Some(BotError::TimedOut),
+            Ok(None) => {}
+            Err(error) => player.stopped = Some(error),
+        }
+        return;
+    }
+
+    if blocked {
+        player.stopped = Some(BotError::Stuck);
+        return;
+    }
+
+    let tiles: Vec<(Position, u32)> = tiles.iter_many(children).map(|(pos, points)| (*pos, points.value)).collect();
+    let walls: Vec<Position> = walls.iter_many(children).copied().collect();
+    let observation = Observation::of(board, &tiles, &walls, progress.score, progress.moves);
+    player.stopped = player.bot.ask(&observation).err();
+    player.asked = Some(Instant::now());
+    player.waiting = true;
+}
+
+// a new game gives the bot another go. one that stopped, or ended the last game still thinking
+// with its answer to come, starts over so no answer of the old game lands on the new board
+pub fn reset_bot(mut player: ResMut<BotPlayer>) {
+    if player.stopped.is_some() || player.waiting {
+        match player.bot.restart() {
+            Ok(bot) => player.bot = bot,
+            Err(error) => {
+                warn!("couldn't restart the bot: {error}");
+                return;
+            }
+        }
+    }
+    player.stopped = None;
+    player.asked = None;
+    player.waiting = false;
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Standing {
+    pub name: String,
+    pub scores: Vec<u32>,
+    pub best_tile: u32,
+    pub moves: u32,
+    pub failures: Vec<String>,  // how the games that didn't end on a full board ended
+}
+
+impl Standing {
+    pub fn mean_score(&self) -> f64 {
+        f64::from(self.scores.iter().sum::<u32>()) / self.scores.len().max(1) as f64
+    }
+}
+
+// every bot plays a game on each seed with the same rules, best mean score first
+pub fn tournament(commands: &[String], seeds: &[u64], config: &EnvConfig, timeout: Duration) -> io::Result<Vec<Standing>> {
+    let mut standings = Vec::new();
+    for command in commands {
+        let mut bot = Bot::spawn(command)?;
+        let mut standing = Standing { name: bot.name.clone(), ..default() };
+        for seed in seeds {
+            let mut env = Env::new(config);
+            let mut observation = env.reset(*seed);
+            let failure = loop {
+                if !env.legal_actions().contains(&true) { break None; }
+                let step = match bot.answer(&observation, timeout) {
+                    Ok(shift) => env.step(shift),
+                    Err(error) => break Some(error),
+                };
+                if step.info.illegal { break Some(BotError::Stuck); }
+                standing.best_tile = standing.best_tile.max(step.info.max_tile);
+                observation = step.observation;
+            };
+            standing.scores.push(observation.score);
+            standing.moves += observation.moves;
+            if let Some(error) = failure {
+                standing.failures.push(format!("seed {seed}: {}", error.describe()));
+                bot = bot.restart()?;
+            }
+        }
+        standings.push(standing);
+    }
+    standings.sort_by(|a, b| b.mean_score().total_cmp(&a.mean_score()));
+    Ok(standings)
+}
+
+pub fn standings_table(standings: &[Standing]) -> String {
+    let mut table = format!("{:<16} {:>6} {:>11} {:>6} {:>9} {:>11} {:>9}\n", "bot", "games", "mean score", "best", "best tile", "mean moves", "failures");
+    for standing in standings {
+        let games = standing.scores.len();
+        table += &format!(
+            "{:<16} {:>6} {:>11.1} {:>6} {:>9} {:>11.1} {:>9}\n",
+            standing.name,
+            games,
+            standing.mean_score(),
+            standing.scores.iter().max().unwrap_or(&0),
+            standing.best_tile,
+            f64::from(standing.moves) / games.max(1) as f64,
+            standing.failures.len(),
+        );
+    }
+    for standing in standings {
+        for failure in &standing.failures {
+            table += &format!("{}: {failure}\n", standing.name);
+        }
+    }
+    table
+}
+
+// a shell script standing in for a bot in some other language, for the tests. it gets a directory
+// of its own for any files it keeps, and both are gone again once dropped
+#[cfg(test)]
+pub struct ScriptBot {
+    dir: std::path::PathBuf,
+    pub command: String,
+}
+
+#[cfg(test)]
+impl ScriptBot {
+    pub fn new(name: &str, body: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("boxes-bots-{}", std::process::id())).join(name);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(format!("{name}.sh"));
+        std::fs::write(&path, body).unwrap();
+        ScriptBot { command: format!("sh {}", path.display()), dir }
+    }
+}
+
+#[cfg(test)]
+impl Drop for ScriptBot {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+        if let Some(scripts) = self.dir.parent() { let _ = std::fs::remove_dir(scripts); }  // once it's empty
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::Variant;
+
+    #[test]
+    fn board_lines_and_answers() {
+        let observation = Observation { width: 2, height: 2, cells: vec![2, 0, 0, 16], blocked: vec![false, true, false, false], score: 0, moves: 0 };
+        assert_eq!(board_line(&observation), "2 2 2 # 0 16");
+        assert_eq!(parse_answer("left"), Ok(BoardShift::Left));
+        assert_eq!(parse_answer(" d"), Ok(BoardShift::Down));
+        assert_eq!(parse_answer("x"), Err(BotError::BadAnswer("x".to_string())));
+    }
+
+    #[test]
+    fn named_after_the_program_or_its_script() {
+        assert_eq!(bot_name("python3 bot.py --depth 3"), "bot");
+        assert_eq!(bot_name("python3.11 -u bots/greedy.py"), "greedy");
+        assert_eq!(bot_name("./bot --fast"), "bot");
+        assert_eq!(bot_name("/usr/local/bin/expectimax"), "expectimax");
+        assert_eq!(bot_name("java -jar corner.jar"), "corner");
+        assert_eq!(bot_name("node"), "node");
+    }
+
+    const CYCLING_BOT: &str = "i=0\nwhile read board; do\n  case $((i % 4)) in 0) echo L;; 1) echo D;; 2) echo R;; 3) echo D;; esac\n  i=$((i + 1))\ndone\n";
+
+    #[test]
+    fn tournament_ranks_bots_over_the_same_seeds() {
+        let config = EnvConfig { width: 4, height: 4, variant: Variant::Classic, four_chance: 0.1, wall_chance: 0.0 };
+        let (left, cycling) = (ScriptBot::new("left", "while read board; do echo L; done\n"), ScriptBot::new("cycling", CYCLING_BOT));
+        let bots = [left.command.clone(), cycling.command.clone()];
+        // plenty of time, a busy machine mustn't turn an answer into a timeout
+        let timeout = Duration::from_secs(30);
+        let standings = tournament(&bots, &[1, 2], &config, timeout).unwrap();
+        assert_eq!(standings, tournament(&bots, &[1, 2], &config, timeout).unwrap(), "fixed seeds, same results");
+
+        let names: Vec<&str> = standings.iter().map(|standing| standing.name.as_str()).collect();
+        assert_eq!(names, vec!["cycling", "left"]);
+        assert!(standings[0].mean_score() > standings[1].mean_score());
+        assert!(standings[1].failures.iter().all(|failure| failure.ends_with("stuck")), "{:?}", standings[1].failures);
+        assert!(standings_table(&standings).lines().next().unwrap().starts_with("bot"));
+    }
+
+    #[test]
+    fn tournament_ends_the_games_of_a_bot_that_doesnt_answer() {
+        let config = EnvConfig { width: 4, height: 4, variant: Variant::Classic, four_chance: 0.1, wall_chance: 0.0 };
+        let silent = ScriptBot::new("silent", "while read board; do :; done\n");
+        let standings = tournament(std::slice::from_ref(&silent.command), &[1, 2], &config, Duration::from_millis(200)).unwrap();
+        assert_eq!(standings[0].scores, vec![0, 0]);
+        assert_eq!(standings[0].failures, vec!["seed 1: timed out", "seed 2: timed out"]);
+    }
+}
@@ -0,0 +1,151 @@
+use std::collections::BTreeMap;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::{Board, Game, GameMode, GameRng, OwnRng, Points, Position, Progress};
+use crate::calendar::format_day;
+use crate::storage::{Profile, Storage};
+
+const DAILY_FILE: &str = "daily";
+// everyone plays the same rules on the daily, whatever their settings
+pub const DAILY_SIZE: u8 = 4;
+pub const DAILY_FOUR_CHANCE: f32 = 0.1;
+
+
+// how the one try of a day went, by yyyy-mm-dd
+#[derive(Resource, Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct DailyRecords {
+    pub days: BTreeMap<String, DailyResult>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct DailyResult {
+    pub score: u32,
+    pub best_tile: u32,
+    pub moves: u32,
+    pub finished: bool,  // false while the try is on, or when the game was left for good halfway
+    pub rows: Vec<Vec<u32>>,  // the final board, top row first, 0 for an empty cell
+}
+
+impl DailyRecords {
+    pub fn get(&self, day: i64) -> Option<&DailyResult> {
+        self.days.get(&format_day(day))
+    }
+
+    pub fn best(&self) -> Option<(&String, &DailyResult)> {
+        self.days.iter().max_by_key(|(_, result)| result.score)
+    }
+}
+
+impl DailyResult {
+    // a few lines to paste in the team chat, the board as colored squares so nothing is spoiled
+    pub fn summary(&self, day: i64) -> String {
+        let mut text = format!(
+            "2048 daily {}\nscore {}, best tile {}, {} moves\n",
+            format_day(day), self.score, self.best_tile, self.moves
+        );
+        for row in &self.rows {
+            text.extend(row.iter().map(|value| square(*value)));
+            text.push('\n');
+        }
+        text
+    }
+}
+
+fn square(value: u32) -> char {
+    match value {
+        0 => '⬛',
+        1..=8 => '⬜',
+        9..=64 => '🟨',
+        65..=256 => '🟧',
+        257..=1024 => '🟥',
+        _ => '🟪',
+    }
+}
+
+// the same day gives the same tiles on every machine
+pub fn daily_seed(day: i64) -> u64 {
+    (day as u64) ^ 0x2048_2048_2048_2048
+}
+
+pub fn load_daily_records(mut records: ResMut<DailyRecords>, storage: Res<Storage>, profile: Res<Profile>) {
+    *records = storage.load(&profile.file(DAILY_FILE));
+}
+
+// the try is used up as soon as the first tiles are down, leaving the game doesn't give it back
+pub fn begin_daily(
+    game: Res<Game>,
+    mut rng: ResMut<GameRng>,
+    mut own: ResMut<OwnRng>,
+    mut records: ResMut<DailyRecords>,
+    storage: Res<Storage>,
+    profile: Res<Profile>,
+) {
+    let GameMode::Daily(day) = game.mode else { return; };
+    rng.share(daily_seed(day), &mut own);
+    records.days.entry(format_day(day)).or_default();
+    storage.save(&profile.file(DAILY_FILE), &*records);
+}
+
+pub fn record_daily_result(
+    game: Res<Game>,
+    query_board: Query<(&Board, &Progress)>,
+    tiles: Query<(&Position, &Points)>,
+    mut records: ResMut<DailyRecords>,
+    storage: Res<Storage>,
+    profile: Res<Profile>,
+) {
+    let GameMode::Daily(day) = game.mode else { return; };
+    let Ok((board, progress)) = query_board.get_single() else { return; };
+
+    let mut rows = vec![vec![0; board.width.into()]; board.height.into()];
+    for (pos, points) in tiles.iter() {
+        rows[usize::from(board.height - 1 - pos.y)][usize::from(pos.x)] = points.value;
+    }
+    records.days.insert(format_day(day), DailyResult {
+        score: progress.score,
+        best_tile: tiles.iter().map(|(_, points)| points.value).max().unwrap_or(0),
+        moves: progress.moves,
+        finished: true,
+        rows,
+    });
+    storage.save(&profile.file(DAILY_FILE), &*records);
+}
+
+pub fn export_daily(day: i64, records: &DailyRecords, storage: &Storage, profile: &Profile) {
+    let Some(result) = records.get(day) else { return; };
+    let name = profile.file(&format!("{DAILY_FILE}/{}", format_day(day)));
+    if let Some(path) = storage.export(&name, &result.summary(day)) {
+        info!("daily summary saved to {}", path.display());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summary_shows_the_board_as_squares() {
+        let result = DailyResult {
+            score: 1234,
+            best_tile: 128,
+            moves: 210,
+            finished: true,
+            rows: vec![vec![128, 2, 0], vec![0, 2048, 32]],
+        };
+        assert_eq!(
+            result.summary(20_744),
+            "2048 daily 2026-10-18\nscore 1234, best tile 128, 210 moves\n🟧⬜⬛\n⬛🟪🟨\n"
+        );
+    }
+
+    #[test]
+    fn best_day_has_the_top_score() {
+        let mut records = DailyRecords::default();
+        records.days.insert("2026-10-17".to_string(), DailyResult { score: 900, ..default() });
+        records.days.insert("2026-10-18".to_string(), DailyResult { score: 300, ..default() });
+        assert_eq!(records.best().map(|(day, _)| day.as_str()), Some("2026-10-17"));
+        assert_eq!(records.get(20_744).map(|result| result.score), Some(300));
+    }
+}
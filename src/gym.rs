@@ -0,0 +1,344 @@
+// a gym style environment for training agents, on the same slide_tiles, deal and random_spawn
+// the game itself runs, so the same seed and moves give the same boards as on screen.
+// `boxes --gym` serves it over stdin and stdout, one json object a line each way:
+//
+//   {"cmd":"make","count":8,"config":{"width":4,"height":4,"variant":"Classic","four_chance":0.1,"wall_chance":0.1}}
+//      -> {"ok":true}                      count environments, the config and any field of it are optional
+//   {"cmd":"reset","seeds":[1,2,3,4,5,6,7,8]}
+//      -> {"observations":[...]}           a seed for every environment
+//   {"cmd":"step","actions":["left","up",...]}
+//      -> {"steps":[{"observation":...,"reward":4,"done":false,"info":{...}}]}
+//   {"cmd":"legal"}
+//      -> {"masks":[[true,false,true,true],...]}    in the order left, right, up, down
+//   anything else -> {"error":"..."}
+//
+// an observation is {"width","height","cells","blocked","score","moves"}, cells row by row from the top left
+// with 0 for an empty cell, blocked true for walls and dead cells. the reward is the points of the merges.
+// a move that slides nothing still counts and brings a new tile, as in the game, with "illegal" in the info.
+// an environment that is done stays done until its next reset
+use std::io::{BufRead, Write};
+use rand::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::{can_slide, deal, free_cells, random_spawn, slide_tiles, Board, BoardShift, Position, Rules, Spawn, SpawnArea};
+use crate::settings::{Settings, Variant, BOARD_SIZES};
+
+pub const ACTIONS: [BoardShift; 4] = [BoardShift::Left, BoardShift::Right, BoardShift::Up, BoardShift::Down];
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct EnvConfig {
+    pub width: u8,
+    pub height: u8,
+    pub variant: Variant,
+    pub four_chance: f32,
+    pub wall_chance: f32,
+}
+
+impl EnvConfig {
+    pub fn from_settings(settings: &Settings) -> Self {
+        EnvConfig {
+            width: settings.board_width,
+            height: settings.board_height,
+            variant: settings.variant,
+            four_chance: settings.four_chance,
+            wall_chance: settings.wall_chance,
+        }
+    }
+}
+
+impl Default for EnvConfig {
+    fn default() -> Self {
+        EnvConfig::from_settings(&Settings::default())
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Observation {
+    pub width: u8,
+    pub height: u8,
+    pub cells: Vec<u32>,
+    pub blocked: Vec<bool>,
+    pub score: u32,
+    pub moves: u32,
+}
+
+impl Observation {
+    // also how the game shows a board to a bot
+    pub fn of(board: &Board, tiles: &[(Position, u32)], walls: &[Position], score: u32, moves: u32) -> Self {
+        let cells = cells_from_top_left(board);
+        Observation {
+            width: board.width,
+            height: board.height,
+            cells: cells.iter()
+                .map(|pos| tiles.iter().find(|(tile, _)| tile == pos).map_or(0, |(_, value)| *value))
+                .collect(),
+            blocked: cells.iter().map(|pos| walls.contains(pos) || board.dead.contains(pos)).collect(),
+            score,
+            moves,
+        }
+    }
+}
+
+fn cells_from_top_left(board: &Board) -> Vec<Position> {
+    (0..board.height).rev()
+        .flat_map(|y| (0..board.width).map(move |x| Position { x, y }))
+        .collect()
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Info {
+    pub illegal: bool,
+    pub max_tile: u32,
+    pub legal: [bool; 4],
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Step {
+    pub observation: Observation,
+    pub reward: u32,
+    pub done: bool,
+    pub info: Info,
+}
+
+pub struct Env {
+    board: Board,
+    rules: Rules,
+    wall_chance: f32,
+    rng: StdRng,
+    tiles: Vec<(Position, u32)>,
+    walls: Vec<Position>,
+    score: u32,
+    moves: u32,
+}
+
+impl Env {
+    pub fn new(config: &EnvConfig) -> Self {
+        let size = |cells: u8| cells.clamp(*BOARD_SIZES.start(), *BOARD_SIZES.end());
+        let mut env = Env {
+            board: Board::new(size(config.width), size(config.height)),
+            rules: Rules { variant: config.variant, four_chance: config.four_chance },
+            wall_chance: config.wall_chance,
+            rng: StdRng::seed_from_u64(0),
+            tiles: Vec::new(),
+            walls: Vec::new(),
+            score: 0,
+            moves: 0,
+        };
+        env.reset(0);
+        env
+    }
+
+    pub fn reset(&mut self, seed: u64) -> Observation {
+        self.rng = StdRng::seed_from_u64(seed);
+        (self.walls, self.tiles) = deal(&self.board, &self.rules, &mut self.rng);
+        self.score = 0;
+        self.moves = 0;
+        self.observation()
+    }
+
+    pub fn step(&mut self, action: BoardShift) -> Step {
+        let mut tiles = self.tiles.clone();
+        let slide = slide_tiles(&self.board, &self.walls, self.rules.variant, action, &mut tiles);
+        let illegal = slide.moved_rows.is_empty();
+
+        self.tiles = tiles.into_iter().enumerate()
+            .filter(|(index, _)| !slide.merged_away.contains(index))
+            .map(|(_, tile)| tile)
+            .collect();
+        self.score += slide.gain;
+        self.moves += 1;
+        // as in board_shift and new_tile_handler
+        let area = if self.rules.variant == Variant::Threes {
+            let mut rows = slide.moved_rows;
+            rows.dedup();
+            SpawnArea::Edge { shift: action, rows }
+        } else {
+            SpawnArea::Anywhere
+        };
+        let (tiles, walls) = (&self.tiles, &self.walls);
+        let free = free_cells(&self.board, &area, |pos| tiles.iter().any(|(tile, _)| tile == pos) || walls.contains(pos));
+        match random_spawn(free, &self.rules, self.wall_chance, &mut self.rng) {
+            Some(Spawn::Tile(position, value)) => self.tiles.push((position, value)),
+            Some(Spawn::Wall(position)) => self.walls.push(position),
+            None => {}
+        }
+        self.step_result(slide.gain, illegal)
+    }
+
+    // which of ACTIONS would slide anything
+    pub fn legal_actions(&self) -> [bool; 4] {
+        ACTIONS.map(|action| can_slide(&self.board, &self.walls, self.rules.variant, action, &self.tiles))
+    }
+
+    pub fn observation(&self) -> Observation {
+        Observation::of(&self.board, &self.tiles, &self.walls, self.score, self.moves)
+    }
+
+    fn step_result(&self, reward: u32, illegal: bool) -> Step {
+        let legal = self.legal_actions();
+        Step {
+            observation: self.observation(),
+            reward,
+            done: !legal.contains(&true),
+            info: Info {
+                illegal,
+                max_tile: self.tiles.iter().map(|(_, value)| *value).max().unwrap_or(0),
+                legal,
+            },
+        }
+    }
+}
+
+// a batch of environments stepped together, one action each
+pub struct VecEnv {
+    pub envs: Vec<Env>,
+}
+
+impl VecEnv {
+    pub fn new(count: usize, config: &EnvConfig) -> Self {
+        VecEnv { envs: (0..count).map(|_| Env::new(config)).collect() }
+    }
+
+    pub fn reset(&mut self, seeds: &[u64]) -> Vec<Observation> {
+        self.envs.iter_mut().zip(seeds).map(|(env, seed)| env.reset(*seed)).collect()
+    }
+
+    pub fn step(&mut self, actions: &[BoardShift]) -> Vec<Step> {
+        self.envs.iter_mut().zip(actions).map(|(env, action)| env.step(*action)).collect()
+    }
+
+    pub fn legal_actions(&self) -> Vec<[bool; 4]> {
+        self.envs.iter().map(Env::legal_actions).collect()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+enum Request {
+    Make {
+        #[serde(default = "one")]
+        count: usize,
+        #[serde(default)]
+        config: EnvConfig,
+    },
+    Reset { seeds: Vec<u64> },
+    Step { actions: Vec<BoardShift> },
+    Legal,
+}
+
+fn one() -> usize {
+    1
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum Response {
+    Ok { ok: bool },
+    Observations { observations: Vec<Observation> },
+    Steps { steps: Vec<Step> },
+    Masks { masks: Vec<[bool; 4]> },
+    Error { error: String },
+}
+
+// until the input runs out, a single environment with the default settings until told otherwise
+pub fn serve(input: impl BufRead, mut output: impl Write) {
+    let mut envs = VecEnv::new(1, &EnvConfig::default());
+    for line in input.lines().map_while(Result::ok) {
+        if line.trim().is_empty() { continue; }
+        let response = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => handle(&mut envs, request),
+            Err(error) => Response::Error { error: error.to_string() },
+        };
+        let text = serde_json::to_string(&response).expect("responses always serialize");
+        if writeln!(output, "{text}").and_then(|_| output.flush()).is_err() { break; }
+    }
+}
+
+fn handle(envs: &mut VecEnv, request: Request) -> Response {
+    let count = envs.envs.len();
+    match request {
+        Request::Make { count, config } => {
+            *envs = VecEnv::new(count, &config);
+            Response::Ok { ok: true }
+        }
+        Request::Reset { seeds } if seeds.len() == count => Response::Observations { observations: envs.reset(&seeds) },
+        Request::Step { actions } if actions.len() == count => Response::Steps { steps: envs.step(&actions) },
+        Request::Reset { .. } | Request::Step { .. } => Response::Error { error: format!("expected one for each of the {count} environments") },
+        Request::Legal => Response::Masks { masks: envs.legal_actions() },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_same_episode() {
+        let config = EnvConfig { width: 4, height: 4, ..default_config() };
+        let (mut first, mut second) = (Env::new(&config), Env::new(&config));
+        assert_eq!(first.reset(5), second.reset(5));
+        for action in [BoardShift::Left, BoardShift::Down, BoardShift::Right, BoardShift::Up].repeat(5) {
+            assert_eq!(first.step(action), second.step(action));
+        }
+        assert_ne!(first.reset(6), second.reset(7));
+    }
+
+    #[test]
+    fn illegal_moves_still_count_like_in_the_game() {
+        let mut env = Env::new(&default_config());
+        env.tiles = vec![(Position { x: 0, y: 0 }, 2), (Position { x: 0, y: 1 }, 4)];
+        assert_eq!(env.legal_actions(), [false, true, true, false]);
+
+        let step = env.step(BoardShift::Left);
+        assert!(step.info.illegal);
+        assert_eq!((step.reward, step.observation.moves), (0, 1));
+        assert_eq!(env.tiles[..2], [(Position { x: 0, y: 0 }, 2), (Position { x: 0, y: 1 }, 4)], "nothing slid");
+        assert_eq!(env.tiles.len(), 3, "but a new tile came in");
+
+        let step = env.step(BoardShift::Right);
+        assert!(!step.info.illegal);
+        assert_eq!(step.observation.moves, 2);
+    }
+
+    #[test]
+    fn full_board_is_done() {
+        let mut env = Env::new(&EnvConfig { width: 3, height: 3, ..default_config() });
+        env.tiles = cells_from_top_left(&env.board).into_iter()
+            .map(|pos| (pos, if (pos.x + pos.y) % 2 == 0 { 2 } else { 4 }))
+            .collect();
+        let step = env.step(BoardShift::Up);
+        assert!(step.done && step.info.illegal);
+        assert_eq!(step.info.legal, [false; 4]);
+        assert_eq!(step.observation.cells, vec![2, 4, 2, 4, 2, 4, 2, 4, 2]);
+    }
+
+    #[test]
+    fn serves_json_lines() {
+        let input = concat!(
+            "{\"cmd\":\"make\",\"count\":2,\"config\":{\"width\":3,\"height\":3}}\n",
+            "{\"cmd\":\"reset\",\"seeds\":[1,2]}\n",
+            "{\"cmd\":\"legal\"}\n",
+            "{\"cmd\":\"step\",\"actions\":[\"left\",\"down\"]}\n",
+            "{\"cmd\":\"step\",\"actions\":[\"left\"]}\n",
+            "jump\n",
+        );
+        let mut output = Vec::new();
+        serve(input.as_bytes(), &mut output);
+        let lines: Vec<serde_json::Value> = String::from_utf8(output).unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(lines.len(), 6);
+        assert_eq!(lines[0], serde_json::json!({"ok": true}));
+        assert_eq!(lines[1]["observations"][1]["cells"].as_array().unwrap().len(), 9);
+        assert_eq!(lines[2]["masks"].as_array().unwrap().len(), 2);
+        assert!(lines[3]["steps"][0]["reward"].is_u64());
+        assert!(lines[4]["error"].as_str().unwrap().contains("2 environments"));
+        assert!(lines[5]["error"].is_string());
+    }
+
+    fn default_config() -> EnvConfig {
+        EnvConfig { variant: Variant::Classic, four_chance: 0.1, wall_chance: 0.0, ..EnvConfig::default() }
+    }
+}
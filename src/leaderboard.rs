@@ -0,0 +1,172 @@
+use std::time::Duration;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::{Board, Game, Points, Progress, Rules};
+use crate::calendar;
+use crate::storage::{Profile, Storage};
+
+pub const LEADERBOARD_SIZE: usize = 10;
+const LEADERBOARD_FILE: &str = "leaderboard";
+
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LeaderboardEntry {
+    pub name: String,
+    pub score: u32,
+    pub max_tile: u32,
+    #[serde(alias = "board_size")]
+    pub board_width: u8,
+    #[serde(default)]
+    pub board_height: u8,  // 0 in entries saved before boards could be rectangular
+    pub variant: String,
+    pub moves: u32,
+    pub duration: Duration,
+    pub date: String,
+}
+
+impl LeaderboardEntry {
+    pub fn board(&self) -> (u8, u8) {
+        (self.board_width, self.board_height)
+    }
+}
+
+// best games on this machine, kept per board size and variant so every filter shows a full table
+#[derive(Resource, Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct Leaderboard {
+    pub entries: Vec<LeaderboardEntry>,
+}
+
+impl Leaderboard {
+    pub fn qualifies(&self, score: u32, board: (u8, u8), variant: &str) -> bool {
+        if score == 0 { return false; }
+        let rivals = self.filtered(Some(board), Some(variant)).count();
+        rivals < LEADERBOARD_SIZE || self.filtered(Some(board), Some(variant))
+            .any(|entry| entry.score < score)
+    }
+
+    pub fn insert(&mut self, entry: LeaderboardEntry) {
+        let (board, variant) = (entry.board(), entry.variant.clone());
+        // among equal scores the older game stays ahead
+        let index = self.entries.partition_point(|other| other.score >= entry.score);
+        self.entries.insert(index, entry);
+
+        let mut rank = 0;
+        self.entries.retain(|entry| {
+            if entry.board() != board || entry.variant != variant { return true; }
+            rank += 1;
+            rank <= LEADERBOARD_SIZE
+        });
+    }
+
+    // best first
+    pub fn filtered<'a>(&'a self, board: Option<(u8, u8)>, variant: Option<&'a str>) -> impl Iterator<Item = &'a LeaderboardEntry> {
+        self.entries.iter().filter(move |entry| {
+            board.is_none_or(|board| entry.board() == board)
+                && variant.is_none_or(|variant| entry.variant == variant)
+        })
+    }
+}
+
+// a finished game good enough for the leaderboard, waiting for the player to type a name
+#[derive(Resource, Default)]
+pub struct PendingEntry(pub Option<LeaderboardEntry>);
+
+pub fn load_leaderboard(mut leaderboard: ResMut<Leaderboard>, storage: Res<Storage>) {
+    *leaderboard = storage.load(LEADERBOARD_FILE);
+    for entry in leaderboard.entries.iter_mut().filter(|entry| entry.board_height == 0) {
+        entry.board_height = entry.board_width;  // those were all square
+    }
+}
+
+pub fn check_high_score(
+    game: Res<Game>,
+    tiles: Query<&Points>,
+    query_board: Query<(&Board, &Rules, &Progress)>,
+    leaderboard: Res<Leaderboard>,
+    profile: Res<Profile>,
+    mut pending: ResMut<PendingEntry>,
+) {
+    let Ok((board, rules, progress)) = query_board.get_single() else { return; };
+    // a shaped board is a different game than the rectangle around it
+    let mut variant = match &board.shape {
+        Some(shape) => format!("{} {shape}", rules.variant.name()),
+        None => rules.variant.name().to_string(),
+    };
+    if let Some(mode) = game.mode.leaderboard_name() {
+        variant = format!("{variant} {mode}");
+    }
+    if !leaderboard.qualifies(progress.score, (board.width, board.height), &variant) { return; }
+
+    pending.0 = Some(LeaderboardEntry {
+        name: profile.name.clone(),
+        score: progress.score,
+        max_tile: tiles.iter().map(|points| points.value).max().unwrap_or(0),
+        board_width: board.width,
+        board_height: board.height,
+        variant,
+        moves: progress.moves,
+        duration: game.elapsed,
+        date: calendar::format_day(calendar::today()),
+    });
+}
+
+pub fn submit_high_score(
+    pending: &mut PendingEntry,
+    leaderboard: &mut Leaderboard,
+    storage: &Storage,
+) {
+    if let Some(entry) = pending.0.take() {
+        leaderboard.insert(entry);
+        storage.save(LEADERBOARD_FILE, leaderboard);
+    }
+}
+
+// a new game started before a name was typed, the score still counts under the default name
+pub fn flush_high_score(
+    mut pending: ResMut<PendingEntry>,
+    mut leaderboard: ResMut<Leaderboard>,
+    storage: Res<Storage>,
+) {
+    submit_high_score(&mut pending, &mut leaderboard, &storage);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::Variant;
+
+    fn entry(score: u32, board_size: u8) -> LeaderboardEntry {
+        LeaderboardEntry {
+            name: format!("p{score}"),
+            score,
+            max_tile: 0,
+            board_width: board_size,
+            board_height: board_size,
+            variant: Variant::Classic.name().to_string(),
+            moves: 0,
+            duration: Duration::ZERO,
+            date: String::new(),
+        }
+    }
+
+    #[test]
+    fn keeps_the_best_per_board_size() {
+        let mut leaderboard = Leaderboard::default();
+        for score in 1..=LEADERBOARD_SIZE as u32 + 2 {
+            leaderboard.insert(entry(score * 10, 4));
+        }
+        leaderboard.insert(entry(5, 5));
+
+        let fours: Vec<u32> = leaderboard.filtered(Some((4, 4)), None).map(|entry| entry.score).collect();
+        assert_eq!(fours.len(), LEADERBOARD_SIZE);
+        assert_eq!(fours.first(), Some(&120));
+        assert_eq!(fours.last(), Some(&30));
+        assert_eq!(leaderboard.filtered(Some((5, 5)), Some(Variant::Classic.name())).count(), 1);
+
+        assert!(!leaderboard.qualifies(30, (4, 4), Variant::Classic.name()));
+        assert!(leaderboard.qualifies(31, (4, 4), Variant::Classic.name()));
+        assert!(leaderboard.qualifies(1, (5, 5), Variant::Classic.name()));
+        assert!(!leaderboard.qualifies(0, (6, 6), Variant::Classic.name()));
+    }
+}
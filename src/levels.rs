@@ -0,0 +1,101 @@
+use std::collections::HashSet;
+use std::fs;
+use bevy::asset::FileAssetIo;
+use bevy::prelude::*;
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use crate::Position;
+
+const SHAPES_FILE: &str = "shapes";
+
+
+// a board as the level files draw it, a row a line with the top row first, as it looks on screen.
+// the cells of a row are space separated: '.' a cell and 'x' a cell that isn't part of the board.
+// puzzles put numbers for their tiles and '#' for walls in it too
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(transparent)]
+pub struct Drawing(pub Vec<String>);
+
+impl Drawing {
+    pub fn cells(&self) -> impl Iterator<Item = (Position, &str)> {
+        let height = self.0.len() as u8;
+        self.0.iter().enumerate().flat_map(move |(row, line)| {
+            line.split_whitespace().enumerate().map(move |(x, token)| {
+                (Position { x: x as u8, y: height - 1 - row as u8 }, token)
+            })
+        })
+    }
+
+    pub fn size(&self) -> (u8, u8) {
+        let width = self.0.iter().map(|row| row.split_whitespace().count()).max().unwrap_or(0);
+        (width as u8, self.0.len() as u8)
+    }
+
+    // short rows are padded with dead cells
+    pub fn dead_cells(&self) -> HashSet<Position> {
+        let (width, height) = self.size();
+        let live: HashSet<Position> = self.cells().filter(|(_, token)| *token != "x").map(|(pos, _)| pos).collect();
+        (0..width).flat_map(|x| (0..height).map(move |y| Position { x, y }))
+            .filter(|pos| !live.contains(pos))
+            .collect()
+    }
+}
+
+// a board that isn't a full rectangle
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct BoardShape {
+    pub name: String,
+    pub rows: Drawing,
+}
+
+#[derive(Resource, Default)]
+pub struct Shapes(pub Vec<BoardShape>);
+
+impl Shapes {
+    pub fn get(&self, name: &str) -> Option<&BoardShape> {
+        self.0.iter().find(|shape| shape.name == name)
+    }
+}
+
+pub fn load_shapes(mut shapes: ResMut<Shapes>) {
+    shapes.0 = read_level_file(SHAPES_FILE);
+}
+
+// level files ship with the game in assets/levels. they are read right away rather than through
+// the asset server, the board can't be laid out before they are known
+pub fn read_level_file<T: DeserializeOwned + Default>(name: &str) -> T {
+    let path = FileAssetIo::get_base_path().join("assets/levels").join(format!("{name}.json"));
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(err) => {
+            warn!("could not read {}: {err}", path.display());
+            return T::default();
+        }
+    };
+    serde_json::from_str(&content).unwrap_or_else(|err| {
+        warn!("{} is broken: {err}", path.display());
+        T::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dead_cells_come_from_the_drawing() {
+        let notch = Drawing(vec![". x .".to_string(), ". .".to_string()]);
+        assert_eq!(notch.size(), (3, 2));
+        assert_eq!(notch.dead_cells(), HashSet::from([Position { x: 1, y: 1 }, Position { x: 2, y: 0 }]));
+    }
+
+    #[test]
+    fn shipped_shapes_load() {
+        let shapes: Vec<BoardShape> = read_level_file(SHAPES_FILE);
+        assert!(shapes.iter().any(|shape| shape.name == "plus"));
+        for shape in shapes {
+            let (width, height) = shape.rows.size();
+            assert!(shape.rows.dead_cells().len() < usize::from(width) * usize::from(height));
+        }
+    }
+}
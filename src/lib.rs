@@ -0,0 +1,1374 @@
+#![allow(clippy::type_complexity, clippy::too_many_arguments)]  // bevy queries and systems are verbose by nature
+
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::time::Duration;
+use bevy::prelude::*;
+use itertools::Itertools;
+use rand::prelude::*;
+use bevy_easings::*;
+use serde::{Deserialize, Serialize};
+use rules::MergeRule;
+
+mod ui;
+use ui::*;
+mod audio;
+use audio::*;
+mod calendar;
+mod daily;
+use daily::*;
+mod leaderboard;
+use leaderboard::*;
+mod stats;
+use stats::*;
+mod settings;
+use settings::*;
+pub use settings::{Settings, Variant};
+mod rules;
+mod levels;
+use levels::*;
+mod puzzles;
+use puzzles::*;
+mod storage;
+use storage::*;
+pub mod protocol;
+mod net;
+use net::*;
+mod spectate;
+use spectate::*;
+pub mod gym;
+mod bot;
+use bot::*;
+
+#[cfg(test)]
+mod tests;
+
+const BOARD_COLOR: Color = Color::rgb(0.7, 0.7, 0.8);
+const TILE_PLACEHOLDER_COLOR: Color = Color::rgb(0.75, 0.75, 0.9);
+const TILE_COLOR: Color = Color::WHITE;
+
+
+// everything the boxes binary does, by its command line: a training environment, a bot tournament or the game
+pub fn run() {
+    // agents in training drive a headless environment over stdin and stdout, see gym.rs
+    if std::env::args().any(|arg| arg == "--gym") {
+        gym::serve(std::io::stdin().lock(), std::io::stdout().lock());
+        return;
+    }
+    // bots as separate programs, see bot.rs
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(at) = args.iter().position(|arg| arg == "--tournament") {
+        let games: u64 = args.get(at + 1).and_then(|games| games.parse().ok()).unwrap_or(10);
+        let seeds: Vec<u64> = (1..=games).collect();
+        match tournament(&args[(at + 2).min(args.len())..], &seeds, &gym::EnvConfig::default(), BOT_TIMEOUT) {
+            Ok(standings) => print!("{}", standings_table(&standings)),
+            Err(error) => eprintln!("couldn't run the tournament: {error}"),
+        }
+        return;
+    }
+    let bot = match args.iter().position(|arg| arg == "--bot").and_then(|at| args.get(at + 1)) {
+        Some(command) => match Bot::spawn(command) {
+            Ok(bot) => Some(BotPlayer::new(bot)),
+            Err(error) => {
+                eprintln!("couldn't start the bot {command:?}: {error}");
+                return;
+            }
+        },
+        None => None,
+    };
+
+    let mut app = App::new();
+    if let Some(bot) = bot { app.insert_resource(bot); }
+    app.add_plugins(DefaultPlugins)
+        .add_plugin(GamePlugin)
+        .add_plugin(GameUiPlugin)
+        .add_plugin(GameAudioPlugin)
+        .add_plugin(EasingsPlugin)
+        .init_resource::<Materials>()
+        .init_resource::<FontSpec>()
+        .add_startup_system(setup)
+        .run();
+}
+
+// game logic only, no window, fonts or ui, so it can be driven by tests with MinimalPlugins
+pub struct GamePlugin;
+
+impl Plugin for GamePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Game>()
+            .init_resource::<GameRng>()
+            .init_resource::<OwnRng>()
+            .init_resource::<Storage>()
+            .init_resource::<Profile>()
+            .init_resource::<Statistics>()
+            .init_resource::<Leaderboard>()
+            .init_resource::<PendingEntry>()
+            .init_resource::<Settings>()
+            .init_resource::<Shapes>()
+            .init_resource::<Puzzles>()
+            .init_resource::<PuzzleRecords>()
+            .init_resource::<DailyRecords>()
+            .init_resource::<Online>()
+            .init_resource::<ServerAddress>()
+            .add_startup_system(load_settings)
+            .add_startup_system(load_shapes)
+            .add_startup_system(load_puzzles)
+            .add_startup_system(load_profile)
+            .add_startup_system(load_puzzle_records.after(load_profile))
+            .add_startup_system(load_daily_records.after(load_profile))
+            .add_startup_system(spawn_boards.after(load_settings).after(load_shapes))
+            .add_startup_system(load_statistics.after(load_profile))
+            .add_startup_system(load_leaderboard)
+            .add_startup_system(start_spectating)
+            .add_event::<NewTileEvent>()
+            .add_event::<SpawnedEvent>()
+            .add_event::<ShiftEvent>()
+            .add_event::<ScoreGainEvent>()
+            .add_event::<MoveEvent>()
+            .add_state::<RunState>()
+            .configure_sets(
+                (GameSet::Input, GameSet::Resolve, GameSet::Spawn, GameSet::Detect, GameSet::Render)
+                    .chain()
+                    .in_set(OnUpdate(RunState::Playing))
+            )
+            .add_systems(
+                // coming back from the pause or the win screen carries on with the same game.
+                // a puzzle brings its own board, it has to be in place before the tiles
+                (game_reset, apply_system_buffers, resize_board, apply_system_buffers, take_back_own_rng, begin_daily, spawn_tiles).chain()
+                .distributive_run_if(no_game_in_progress)
+                .in_schedule(OnEnter(RunState::Playing))
+            )
+            .add_system(keyboard_shift.in_set(GameSet::Input))
+            .add_system(bot_turn.in_set(GameSet::Input).run_if(resource_exists::<BotPlayer>()))
+            .add_system(
+                reset_bot.before(game_reset)
+                .run_if(resource_exists::<BotPlayer>()).run_if(no_game_in_progress)
+                .in_schedule(OnEnter(RunState::Playing))
+            )
+            .add_systems((undo_move, board_shift).chain().in_set(GameSet::Resolve))
+            .add_system(new_tile_handler.in_set(GameSet::Spawn))
+            // a puzzle solved with the last possible move is still solved
+            .add_systems((win_check, end_game, puzzle_check).chain().in_set(GameSet::Detect))
+            .add_system(escape_key)
+            .add_systems((resize_board, apply_theme).chain().distributive_run_if(resource_changed::<Settings>()))
+            .add_system(label_boards)
+            .add_system(connect_to_server.in_schedule(OnEnter(RunState::Lobby)))
+            .add_system(await_opponent.in_set(OnUpdate(RunState::Lobby)))
+            .add_system(receive_opponent.in_set(GameSet::Input).run_if(playing_versus))
+            .add_system(hang_up.in_schedule(OnEnter(RunState::MainMenu)))
+            .add_system(hang_up.in_schedule(OnEnter(RunState::GameOver)))
+            .add_system(publish_state.run_if(resource_exists::<Spectators>()).run_if(resource_changed::<State<RunState>>()))
+            // boards go to the opponent and the spectators after the commands of the frame are applied,
+            // so a move goes out with its new tile
+            .add_systems((send_board, send_garbage).distributive_run_if(playing_versus).in_base_set(CoreSet::PostUpdate))
+            .add_systems((publish_boards, publish_moves).distributive_run_if(resource_exists::<Spectators>()).in_base_set(CoreSet::PostUpdate))
+            .add_system(save_settings.in_schedule(OnExit(RunState::Settings)))
+            // another profile comes with its own statistics and records
+            .add_systems((save_profile, load_statistics, load_puzzle_records, load_daily_records).distributive_run_if(profile_switched))
+            .add_systems((render_tile_points, render_tiles).in_set(GameSet::Render))
+            .add_system(game_clock.in_set(OnUpdate(RunState::Playing)))
+            // a lost puzzle isn't a game for the statistics or the leaderboard
+            .add_systems((
+                record_statistics.run_if(not(playing_puzzle)),
+                check_high_score.run_if(not(playing_puzzle)),
+                record_daily_result,
+                finish_game,
+            ).in_schedule(OnEnter(RunState::GameOver)))
+            .add_systems((record_solved_puzzle, finish_game).in_schedule(OnEnter(RunState::Solved)))
+            .add_system(flush_high_score.in_schedule(OnEnter(RunState::Playing)))
+            .add_systems(
+                (
+                    // merged tiles are despawned before a new tile looks for a free cell
+                    apply_system_buffers.after(GameSet::Resolve).before(GameSet::Spawn),
+                    // and the new tile exists before end_game looks for a move
+                    apply_system_buffers.after(GameSet::Spawn).before(GameSet::Detect),
+                ).in_set(OnUpdate(RunState::Playing))
+            );
+    }
+}
+
+// the game systems with a fixed seed and keyboard input, nothing loaded from or saved to disk.
+// the tests and the benchmarks drive it frame by frame
+pub fn headless_app(seed: u64) -> App {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .insert_resource(GameRng::seeded(seed))
+        .insert_resource(FontSpec { family: Handle::default(), mono: Handle::default() })
+        .insert_resource(Storage::in_memory())
+        .init_resource::<Input<KeyCode>>()
+        .add_plugin(GamePlugin);
+    app
+}
+
+// one move goes through these in order within a single frame
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+enum GameSet {
+    Input,
+    Resolve,
+    Spawn,
+    Detect,
+    Render,
+}
+
+// part 2
+
+fn setup(mut commands: Commands) {
+    commands.spawn(Camera2dBundle::default());
+}
+
+const TILE_SIZE: f32 = 40.0;
+const TILE_SPACER: f32 = 10.0;
+
+#[derive(Component, Debug, Clone, PartialEq)]
+pub struct Board {
+    width: u8,
+    height: u8,
+    physical_size: Vec2,
+    shape: Option<String>,
+    dead: HashSet<Position>,  // cells of the shape that are not part of the board, nothing goes there
+}
+
+
+impl Board {
+    pub fn new(width: u8, height: u8) -> Self {
+        let physical_length = |cells: u8| f32::from(cells) * TILE_SIZE + f32::from(cells + 1) * TILE_SPACER;
+        let physical_size = Vec2::new(physical_length(width), physical_length(height));
+        Board {width, height, physical_size, shape: None, dead: HashSet::new()}
+    }
+
+    fn shaped(shape: &BoardShape) -> Self {
+        let (width, height) = shape.rows.size();
+        Board { shape: Some(shape.name.clone()), dead: shape.rows.dead_cells(), ..Board::new(width, height) }
+    }
+
+    fn for_puzzle(puzzle: &Puzzle) -> Self {
+        let (width, height) = puzzle.rows.size();
+        Board { dead: puzzle.rows.dead_cells(), ..Board::new(width, height) }
+    }
+
+    // a puzzle brings its own board, otherwise it is up to the settings.
+    // a shape that didn't load falls back to the plain rectangle
+    fn for_game(game: &Game, settings: &Settings, shapes: &Shapes, puzzles: &Puzzles) -> Self {
+        match game.mode {
+            GameMode::Puzzle(index) => {
+                if let Some(puzzle) = puzzles.0.get(index) { return Board::for_puzzle(puzzle); }
+            }
+            GameMode::Daily(_) => return Board::new(DAILY_SIZE, DAILY_SIZE),
+            GameMode::Versus => return Board::new(protocol::VERSUS_SIZE, protocol::VERSUS_SIZE),
+            GameMode::Endless | GameMode::TimeAttack | GameMode::MoveLimit | GameMode::Race | GameMode::Compare => {}
+        }
+        match settings.shape.as_deref().and_then(|name| shapes.get(name)) {
+            Some(shape) => Board::shaped(shape),
+            None => Board::new(settings.board_width, settings.board_height),
+        }
+    }
+
+    fn cell_position_to_physical(&self, pos: Position) -> Vec2 {
+        let offset = -self.physical_size / 2.0 + 0.5 * TILE_SIZE;
+        let cell = Vec2::new(f32::from(pos.x), f32::from(pos.y));
+        offset + cell * TILE_SIZE + (cell + 1.0) * TILE_SPACER
+    }
+
+    fn cells(&self) -> impl Iterator<Item = Position> + '_ {
+        (0..self.width).cartesian_product(0..self.height)
+            .map(|(x, y)| Position { x, y })
+            .filter(|pos| !self.dead.contains(pos))
+    }
+
+    fn cell_count(&self) -> usize {
+        usize::from(self.width) * usize::from(self.height) - self.dead.len()
+    }
+}
+
+#[derive(Component)]
+struct TilePlaceholder;
+
+// whose keys move a board
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Controls {
+    Bindings,  // the keys from the settings
+    Wasd,
+    Arrows,
+    Remote,  // the opponent online, mirrored from what comes over the network
+}
+
+impl Controls {
+    fn shift_for(&self, key: KeyCode, bindings: &KeyBindings) -> Option<BoardShift> {
+        match self {
+            Controls::Bindings => bindings.shift_for(key),
+            Controls::Wasd => KeyBindings { left: KeyCode::A, right: KeyCode::D, up: KeyCode::W, down: KeyCode::S, ..*bindings }
+                .shift_for(key),
+            Controls::Arrows => KeyBindings { left: KeyCode::Left, right: KeyCode::Right, up: KeyCode::Up, down: KeyCode::Down, ..*bindings }
+                .shift_for(key),
+            Controls::Remote => None,
+        }
+    }
+
+    // the players of a race or a versus, or the rules when one player moves all boards
+    fn board_name(&self, rules: &Rules, mode: GameMode) -> &'static str {
+        match self {
+            Controls::Bindings if mode == GameMode::Compare => rules.variant.name(),
+            Controls::Bindings => "You",
+            Controls::Wasd => "Player 1",
+            Controls::Arrows => "Player 2",
+            Controls::Remote => "Opponent",
+        }
+    }
+
+    // the board of whoever sits at this screen, when it isn't one player on all of them
+    fn is_you(&self, mode: GameMode) -> bool {
+        *self == Controls::Bindings && mode != GameMode::Compare
+    }
+}
+
+// a name over each board when more than one is playing
+#[derive(Component)]
+struct BoardLabel;
+
+// where a board is in the layout, left to right and then top to bottom
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct BoardIndex(usize);
+
+// the rules a board plays by, set when its game starts
+#[derive(Component, Debug, Clone, Copy, Default, PartialEq)]
+struct Rules {
+    variant: Variant,
+    four_chance: f32,
+}
+
+impl Rules {
+    // the settings, unless the mode has rules of its own. comparing the variants deals one to each board
+    fn for_board(game: &Game, index: BoardIndex, settings: &Settings, puzzles: &Puzzles) -> Self {
+        let from_settings = Rules { variant: settings.variant, four_chance: settings.four_chance };
+        match game.mode {
+            // the daily, and online where both boards have to play alike
+            GameMode::Daily(_) => Rules { variant: Variant::Classic, four_chance: DAILY_FOUR_CHANCE },
+            GameMode::Versus => Rules { variant: Variant::Classic, four_chance: protocol::VERSUS_FOUR_CHANCE },
+            GameMode::Puzzle(puzzle) => match puzzles.0.get(puzzle) {
+                Some(puzzle) => Rules { variant: puzzle.variant, ..from_settings },
+                None => from_settings,
+            },
+            GameMode::Compare => Rules { variant: Variant::ALL[index.0 % Variant::ALL.len()], ..from_settings },
+            GameMode::Endless | GameMode::TimeAttack | GameMode::MoveLimit | GameMode::Race => from_settings,
+        }
+    }
+}
+
+// how the game on one board is going
+#[derive(Component, Debug, Clone, Default, PartialEq)]
+struct Progress {
+    score: u32,
+    moves: u32,
+    won: bool,
+    spawn_queue: Option<Vec<u32>>,  // what comes in after each move when it isn't random
+}
+
+// the two player race puts a board for each side by side, comparing the variants one for each
+fn boards_for_game(game: &Game, settings: &Settings, shapes: &Shapes, puzzles: &Puzzles) -> Vec<(Board, Controls)> {
+    let board = Board::for_game(game, settings, shapes, puzzles);
+    match game.mode {
+        GameMode::Race => vec![(board.clone(), Controls::Wasd), (board, Controls::Arrows)],
+        GameMode::Versus => vec![(board.clone(), Controls::Bindings), (board, Controls::Remote)],
+        GameMode::Compare => vec![(board, Controls::Bindings); Variant::ALL.len()],
+        _ => vec![(board, Controls::Bindings)],
+    }
+}
+
+fn spawn_boards(mut commands: Commands, font_spec: Res<FontSpec>, game: Res<Game>, settings: Res<Settings>, shapes: Res<Shapes>, puzzles: Res<Puzzles>) {
+    let boards = boards_for_game(&game, &settings, &shapes, &puzzles);
+    spawn_board_entities(&mut commands, &font_spec, boards, &settings.theme.palette());
+}
+
+// in a grid about as wide as it is high, centered on the middle of the window.
+// every cell of the grid is as big as the biggest board, with room for the label over it
+fn board_layout(sizes: &[Vec2]) -> Vec<Vec2> {
+    let columns = (sizes.len() as f32).sqrt().ceil().max(1.0) as usize;
+    let rows = sizes.len().div_ceil(columns);
+    let gap = 2.0 * TILE_SIZE;
+    let cell = sizes.iter().fold(Vec2::ZERO, |biggest, size| biggest.max(*size)) + gap;
+    (0..sizes.len()).map(|index| {
+        let (column, row) = ((index % columns) as f32, (index / columns) as f32);
+        Vec2::new(column - (columns - 1) as f32 / 2.0, (rows - 1) as f32 / 2.0 - row) * cell
+    }).collect()
+}
+
+fn spawn_board_entities(commands: &mut Commands, font_spec: &FontSpec, boards: Vec<(Board, Controls)>, palette: &Palette) {
+    let sizes: Vec<Vec2> = boards.iter().map(|(board, _)| board.physical_size).collect();
+    let labelled = boards.len() > 1;
+    for (index, ((board, controls), at)) in boards.into_iter().zip(board_layout(&sizes)).enumerate() {
+        spawn_board_entity(commands, font_spec, board, controls, BoardIndex(index), at, labelled, palette);
+    }
+}
+
+fn spawn_board_entity(
+    commands: &mut Commands,
+    font_spec: &FontSpec,
+    board: Board,
+    controls: Controls,
+    index: BoardIndex,
+    at: Vec2,
+    labelled: bool,
+    palette: &Palette,
+) {
+    commands.spawn(
+        SpriteBundle{
+            sprite: Sprite {
+                color: palette.board,
+                custom_size: Some(board.physical_size),
+                ..default()
+            },
+            transform: Transform::from_translation(at.extend(0.0)),
+            ..default()
+        },
+    ).with_children(|builder| {
+        for tile in board.cells() {
+            builder.spawn(SpriteBundle {
+                sprite: Sprite {
+                    color: palette.placeholder,
+                    custom_size: Some(Vec2::new(TILE_SIZE, TILE_SIZE)),
+                    ..default()
+                },
+                transform: Transform::from_translation(board.cell_position_to_physical(tile).extend(1.0)),
+                ..default()
+            }).insert(TilePlaceholder);
+        }
+        if labelled {
+            builder.spawn(Text2dBundle {
+                text: Text::from_section(
+                    "",
+                    TextStyle {
+                        font: font_spec.family.clone(),
+                        font_size: 30.0,
+                        color: palette.tile_text,
+                    })
+                    .with_alignment(TextAlignment::Center),
+                transform: Transform::from_xyz(0.0, board.physical_size.y / 2.0 + TILE_SIZE / 2.0, 1.0),
+                ..default()
+            }).insert(BoardLabel);
+        }
+    })
+        .insert(board)
+        .insert(controls)
+        .insert(index)
+        .insert(Rules::default())
+        .insert(Progress::default())
+        .insert(UndoHistory::default());
+}
+
+fn label_boards(
+    boards: Query<(&Controls, &Rules, &Children), Changed<Rules>>,
+    mut labels: Query<&mut Text, With<BoardLabel>>,
+    game: Res<Game>,
+) {
+    for (controls, rules, children) in boards.iter() {
+        let mut texts = labels.iter_many_mut(children);
+        while let Some(mut text) = texts.fetch_next() {
+            text.sections[0].value = controls.board_name(rules, game.mode).to_string();
+        }
+    }
+}
+
+// a new board size or shape, or another number of players, throws away the game in progress
+// and starts over on fresh boards. tiles go with their board
+fn resize_board(
+    mut commands: Commands,
+    font_spec: Res<FontSpec>,
+    settings: Res<Settings>,
+    shapes: Res<Shapes>,
+    puzzles: Res<Puzzles>,
+    boards: Query<(Entity, &Board, &Controls, &BoardIndex)>,
+    mut game: ResMut<Game>,
+) {
+    let wanted = boards_for_game(&game, &settings, &shapes, &puzzles);
+    if boards.iter().len() == wanted.len()
+        && boards.iter().all(|(_, board, controls, index)| wanted.get(index.0) == Some(&(board.clone(), *controls))) {
+        return;
+    }
+
+    for (entity, _, _, _) in boards.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    spawn_board_entities(&mut commands, &font_spec, wanted, &settings.theme.palette());
+    game.in_progress = false;
+}
+
+fn apply_theme(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    mut sprites: ParamSet<(
+        Query<&mut Sprite, With<Board>>,
+        Query<&mut Sprite, With<TilePlaceholder>>,
+        Query<&mut Sprite, With<Points>>,
+        Query<&mut Sprite, With<Wall>>,
+    )>,
+    mut texts: Query<&mut Text, Or<(With<TileText>, With<BoardLabel>)>>,
+) {
+    let palette = settings.theme.palette();
+    commands.insert_resource(ClearColor(palette.background));
+    for mut sprite in sprites.p0().iter_mut() { sprite.color = palette.board; }
+    for mut sprite in sprites.p1().iter_mut() { sprite.color = palette.placeholder; }
+    for mut sprite in sprites.p2().iter_mut() { sprite.color = palette.tile; }
+    for mut sprite in sprites.p3().iter_mut() { sprite.color = palette.wall; }
+    for mut text in texts.iter_mut() {
+        for section in text.sections.iter_mut() { section.style.color = palette.tile_text; }
+    }
+}
+
+// part 3
+
+// not used, but presents in the reference
+#[allow(dead_code)]
+#[derive(Resource)]
+struct Materials {
+    board: Handle<ColorMaterial>,
+    tile_placeholder: Handle<ColorMaterial>,
+}
+
+impl FromWorld for Materials {
+    fn from_world(world: &mut World) -> Self {
+        let mut materials = world.get_resource_mut::<Assets<ColorMaterial>>().unwrap();
+        Materials {
+            board: materials.add(BOARD_COLOR.into()),
+            tile_placeholder: materials.add(TILE_PLACEHOLDER_COLOR.into()),
+        }
+    }
+}
+
+// part 6
+
+#[derive(Component, Debug, PartialEq, Eq)]
+struct Points {
+    value: u32,
+}
+
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Position {
+    pub x: u8,
+    pub y: u8,
+}
+
+#[derive(Component)]
+struct TileText;
+
+// an immovable cell of the walls variant, tiles neither pass through nor merge with it
+#[derive(Component)]
+struct Wall;
+
+
+fn spawn_tiles(
+    mut commands: Commands,
+    mut boards: Query<(Entity, &Board, &Controls, &BoardIndex, &mut Rules, &mut Progress)>,
+    font_spec: Res<FontSpec>,
+    mut rng: ResMut<GameRng>,
+    mut game: ResMut<Game>,
+    settings: Res<Settings>,
+    puzzles: Res<Puzzles>,
+) {
+    game.in_progress = true;
+    let puzzle = match game.mode { GameMode::Puzzle(index) => puzzles.0.get(index), _ => None };
+    let palette = settings.theme.palette();
+
+    for (entity, board, controls, index, mut rules, mut progress) in boards.iter_mut() {
+        *rules = Rules::for_board(&game, *index, &settings, &puzzles);
+        progress.spawn_queue = None;
+        // the opponent's tiles come over the network
+        if *controls == Controls::Remote { continue; }
+
+        // a puzzle starts the same every time, nothing random about it
+        if let Some(puzzle) = puzzle {
+            progress.spawn_queue = Some(puzzle.spawns.clone());
+            for pos in puzzle.walls() {
+                spawn_wall(&mut commands, entity, board, &palette, pos);
+            }
+            for (pos, value) in puzzle.tiles() {
+                spawn_tile(&mut commands, entity, board, &font_spec, &palette, pos, value);
+            }
+            continue;
+        }
+
+        let (walls, starting_tiles) = deal(board, &rules, &mut rng.0);
+        for pos in walls {
+            spawn_wall(&mut commands, entity, board, &palette, pos);
+        }
+        for (pos, value) in starting_tiles {
+            spawn_tile(&mut commands, entity, board, &font_spec, &palette, pos, value);
+        }
+    }
+}
+
+// the walls and tiles a board starts out with
+fn deal(board: &Board, rules: &Rules, rng: &mut StdRng) -> (Vec<Position>, Vec<(Position, u32)>) {
+    let wall_count = if rules.variant == Variant::Walls { board.width.min(board.height) / 2 } else { 0 };
+    // threes starts out with half the board dealt
+    let tile_count = if rules.variant == Variant::Threes { board.cell_count() / 2 } else { 2 };
+    let cells = board.cells().choose_multiple(rng, tile_count + usize::from(wall_count));
+    let (walls, starting_tiles) = cells.split_at(wall_count.into());
+    let tiles = starting_tiles.iter()
+        .map(|pos| (*pos, rules.variant.merge_rule().new_tile_value(rng, rules.four_chance)))
+        .collect();
+    (walls.to_vec(), tiles)
+}
+
+// part 7
+
+#[derive(Resource)]
+struct FontSpec {
+    family: Handle<Font>,
+    mono: Handle<Font>,
+}
+
+impl FromWorld for FontSpec {
+    fn from_world(world: &mut World) -> Self {
+        let asset_sever = world.get_resource_mut::<AssetServer>().unwrap();
+        FontSpec {
+            family: asset_sever.load("fonts/FiraSans-Bold.ttf"),
+            mono: asset_sever.load("fonts/FiraMono-Medium.ttf"),
+        }
+    }
+}
+
+// part 8
+
+fn render_tile_points(
+    mut texts: Query<&mut Text, With<TileText>>,
+    tiles: Query<(&Points, &Children)>,
+) {
+    for (point, children) in tiles.iter() {
+        if let Some(entry) = children.first() {
+            let mut text = texts.get_mut(*entry).expect("Text expected to exist");
+            let text_section = text.sections.first_mut().expect("first sections as mut expected");
+            text_section.value = point.value.to_string()
+        }
+    }
+}
+
+// part 9
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BoardShift {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+// a move requested by whatever drives the board
+struct ShiftEvent {
+    board: Entity,
+    shift: BoardShift,
+}
+
+fn keyboard_shift(
+    input: Res<Input<KeyCode>>,
+    settings: Res<Settings>,
+    boards: Query<(Entity, &Controls)>,
+    mut shift_writer: EventWriter<ShiftEvent>,
+) {
+    for (board, controls) in boards.iter() {
+        let shift_direction = input.get_just_pressed().find_map(
+            |key_kode| controls.shift_for(*key_kode, &settings.key_bindings)
+        );
+
+        if let Some(shift) = shift_direction {
+            shift_writer.send(ShiftEvent { board, shift });
+        }
+    }
+}
+
+fn board_shift(
+    mut commands: Commands,
+    mut shift_reader: EventReader<ShiftEvent>,
+    mut tiles: Query<(Entity, &mut Position, &mut Points, &Parent)>,
+    query_walls: Query<(&Position, &Parent), (With<Wall>, Without<Points>)>,
+    mut boards: Query<(&Board, &Rules, &mut Progress, &mut UndoHistory)>,
+    mut tile_writer: EventWriter<NewTileEvent>,
+    mut gain_writer: EventWriter<ScoreGainEvent>,
+    mut move_writer: EventWriter<MoveEvent>,
+    mut game: ResMut<Game>,
+    settings: Res<Settings>,
+) {
+    // a single move per board and frame, the despawns of its merges have to be applied before the next one
+    let mut shifts: Vec<(Entity, BoardShift)> = Vec::new();
+    for event in shift_reader.iter() {
+        if !shifts.iter().any(|(board, _)| *board == event.board) { shifts.push((event.board, event.shift)); }
+    }
+
+    for (board_entity, board_shift) in shifts {
+        let Ok((board, rules, mut progress, mut history)) = boards.get_mut(board_entity) else { continue; };
+        let walls: Vec<Position> = query_walls.iter()
+            .filter(|(_, parent)| parent.get() == board_entity)
+            .map(|(position, _)| *position)
+            .collect();
+        let snapshot = Snapshot {
+            tiles: tiles.iter()
+                .filter(|tile| tile.3.get() == board_entity)
+                .map(|(_, position, points, _)| (*position, points.value))
+                .collect(),
+            walls: walls.clone(),
+            progress: progress.clone(),
+        };
+
+        let entities: Vec<Entity> = tiles.iter()
+            .filter(|tile| tile.3.get() == board_entity)
+            .map(|(entity, _, _, _)| entity)
+            .collect();
+        let mut cells: Vec<(Position, u32)> = snapshot.tiles.clone();
+        let slide = slide_tiles(board, &walls, rules.variant, board_shift, &mut cells);
+        for (entity, (position, value)) in entities.iter().zip(cells) {
+            let Ok((_, mut tile_position, mut points, _)) = tiles.get_mut(*entity) else { continue; };
+            *tile_position = position;
+            if points.value != value { points.value = value; }
+        }
+        for index in &slide.merged_away {
+            commands.entity(entities[*index]).despawn_recursive();
+        }
+        let gain = ScoreGainEvent { board: board_entity, total: slide.gain, merges: slide.merges };
+        let mut moved_rows = slide.moved_rows;
+        let blocked = moved_rows.is_empty();
+
+        // no taking back the daily or a race, everyone gets the one try
+        let undo_limit = if let GameMode::Daily(_) | GameMode::Race | GameMode::Versus = game.mode { 0 } else { settings.undo_limit };
+        history.push(snapshot, undo_limit);
+        progress.score += gain.total;
+        progress.moves += 1;
+        game.score_best = game.score_best.max(progress.score);
+        let area = if rules.variant == Variant::Threes {
+            moved_rows.dedup();
+            SpawnArea::Edge { shift: board_shift, rows: moved_rows }
+        } else {
+            SpawnArea::Anywhere
+        };
+        tile_writer.send(NewTileEvent { board: board_entity, area });
+        // nothing could slide that way, it still counts as a move but only gets its own sound
+        if blocked {
+            move_writer.send(MoveEvent::Blocked { board: board_entity });
+        } else {
+            move_writer.send(MoveEvent::Slid { board: board_entity, shift: board_shift });
+        }
+        if gain.total > 0 { gain_writer.send(gain); }
+    }
+}
+
+// what a move did to the tiles of a board
+#[derive(Default)]
+pub struct Slide {
+    merged_away: Vec<usize>,  // indexes of the tiles that merged into another one and are gone
+    gain: u32,
+    merges: Vec<(Position, u32)>,
+    moved_rows: Vec<u8>,  // empty when nothing could move that way
+}
+
+// the rules of a move on plain data: `tiles` get their new positions and values in place.
+// board_shift runs it on the tiles of a board, the gym environment on its own grids
+pub fn slide_tiles(board: &Board, walls: &[Position], variant: Variant, board_shift: BoardShift, tiles: &mut [(Position, u32)]) -> Slide {
+    let rule = variant.merge_rule();
+    // dead cells of a shaped board stop tiles just like walls.
+    // the index just past the last wall of a row between two indexes, where a tile coming from `to` stops
+    let past_wall = |row: u8, from: u8, to: u8| walls.iter().chain(board.dead.iter())
+        .filter(|wall| board_shift.get_row_position(wall) == row)
+        .map(|wall| board_shift.get_column_position(board, wall))
+        .filter(|index| (from..to).contains(index))
+        .max()
+        .map(|index| index + 1);
+
+    let mut slide = Slide::default();
+    let sorted: Vec<usize> = (0..tiles.len()).sorted_by(|a, b| board_shift.sort(&tiles[*a].0, &tiles[*b].0)).collect();
+    if variant == Variant::Threes {
+        step_tiles(board_shift, board, rule, tiles, &sorted, past_wall, &mut slide);
+        return slide;
+    }
+
+    let mut it = sorted.into_iter().peekable();
+    let mut column: u8 = 0;  // when sliding left, the column of the first sorted tile is 0 unless a wall is in the way
+
+    while let Some(tile) = it.next() {
+        let before = tiles[tile].0;
+        let row = board_shift.get_row_position(&before);
+        if let Some(start) = past_wall(row, column, board_shift.get_column_position(board, &before)) {
+            column = start;  // a new segment of the row
+        }
+        board_shift.set_column_position(board, &mut tiles[tile].0, column);
+        if tiles[tile].0 != before { slide.moved_rows.push(row); }
+
+        let Some(&next) = it.peek() else { continue; };
+
+        if row != board_shift.get_row_position(&tiles[next].0) { column = 0; }  // different rows, don't merge
+        else if !rule.merges(tiles[tile].1, tiles[next].1)
+            || past_wall(row, column, board_shift.get_column_position(board, &tiles[next].0)).is_some() {
+            column += 1;  // values the rule doesn't merge stay apart, as do tiles on both sides of a wall
+        }
+        else {
+            it.next();  // the peeked one merges in
+            tiles[tile].1 = rule.merged(tiles[tile].1, tiles[next].1);
+            slide.merged_away.push(next);
+            slide.gain += tiles[tile].1;
+            slide.merges.push(tiles[tile]);
+            slide.moved_rows.push(row);
+
+            if let Some(&future) = it.peek() {
+                if board_shift.get_row_position(&tiles[tile].0) != board_shift.get_row_position(&tiles[future].0) {
+                    column = 0; // next tile on a next row
+                }
+                else { column += 1; }
+            }
+        }
+    }
+    slide
+}
+
+// whether a move that way would move or merge anything, without making it.
+// the game is over and the gym environment done when no direction can
+fn can_slide(board: &Board, walls: &[Position], variant: Variant, board_shift: BoardShift, tiles: &[(Position, u32)]) -> bool {
+    !slide_tiles(board, walls, variant, board_shift, &mut tiles.to_vec()).moved_rows.is_empty()
+}
+
+// threes: every tile moves a single cell, into a free one or onto a tile it merges with.
+// `sorted` has the tiles in order from the edge they move to, as for the full slide
+fn step_tiles(
+    board_shift: BoardShift,
+    board: &Board,
+    rule: &dyn MergeRule,
+    tiles: &mut [(Position, u32)],
+    sorted: &[usize],
+    past_wall: impl Fn(u8, u8, u8) -> Option<u8>,
+    slide: &mut Slide,
+) {
+    // the nearest tile towards the edge in the current row, and whether it took a merge already
+    let mut ahead: Option<(usize, bool)> = None;
+
+    for &tile in sorted {
+        let position = tiles[tile].0;
+        let row = board_shift.get_row_position(&position);
+        let index = board_shift.get_column_position(board, &position);
+        if ahead.is_some_and(|(other, _)| board_shift.get_row_position(&tiles[other].0) != row) { ahead = None; }
+
+        if index == 0 || past_wall(row, index - 1, index).is_some() {
+            ahead = Some((tile, false));  // against the edge or a wall
+            continue;
+        }
+        match ahead {
+            Some((other, merged)) if board_shift.get_column_position(board, &tiles[other].0) == index - 1 => {
+                if merged || !rule.merges(tiles[other].1, tiles[tile].1) {
+                    ahead = Some((tile, false));
+                    continue;
+                }
+                let value = rule.merged(tiles[other].1, tiles[tile].1);
+                tiles[other].1 = value;
+                slide.merged_away.push(tile);
+                slide.gain += value;
+                slide.merges.push((tiles[other].0, value));
+                ahead = Some((other, true));
+            }
+            _ => {
+                board_shift.set_column_position(board, &mut tiles[tile].0, index - 1);
+                ahead = Some((tile, false));
+            }
+        }
+        slide.moved_rows.push(row);
+    }
+}
+
+// whether a move went anywhere, for the sounds and the spectators
+enum MoveEvent {
+    Slid { board: Entity, shift: BoardShift },
+    Blocked { board: Entity },
+}
+
+// points won by one move, and where each merge happened
+struct ScoreGainEvent {
+    board: Entity,
+    total: u32,
+    merges: Vec<(Position, u32)>,
+}
+
+
+// part 12
+
+fn render_tiles(
+    mut commands: Commands,
+    mut tiles: Query<(Entity, &Transform, &Position, &Parent), Changed<Position>>,
+    boards: Query<&Board>,
+    settings: Res<Settings>,
+) {
+    let duration = Duration::from_millis(100).div_f32(settings.animation_speed);
+    for (entity, transform, pos, parent) in tiles.iter_mut() {
+        let Ok(board) = boards.get(parent.get()) else { continue; };
+        let cell = board.cell_position_to_physical(*pos);
+        commands.entity(entity).insert(transform.ease_to(
+            Transform::from_translation(cell.extend(transform.translation.z)),
+            EaseFunction::QuadraticInOut,
+            EasingType::Once { duration }
+        ));
+    }
+}
+
+// part 13
+
+impl BoardShift {
+    fn sort(&self, a: &Position, b: &Position) -> Ordering {
+        match self {
+            BoardShift::Left => {
+                match Ord::cmp(&a.y, &b.y) {
+                    Ordering::Equal => { Ord::cmp(&a.x, &b.x) }
+                    o => o,
+                }
+            }
+            BoardShift::Right => {
+                match Ord::cmp(&b.y, &a.y) {
+                    Ordering::Equal => { Ord::cmp(&b.x, &a.x) }
+                    o => o,
+                }
+            }
+            BoardShift::Up => {
+                match Ord::cmp(&b.x, &a.x) {
+                    Ordering::Equal => { Ord::cmp(&b.y, &a.y) }
+                    o => o,
+                }
+            }
+            BoardShift::Down => {
+                match Ord::cmp(&a.x, &b.x) {
+                    Ordering::Equal => { Ord::cmp(&a.y, &b.y) }
+                    o => o,
+                }
+            }
+        }
+    }
+
+    fn set_column_position(&self, board: &Board, position: &mut Position, index: u8) {
+        match self {
+            BoardShift::Left => { position.x = index; }
+            BoardShift::Right => { position.x = board.width - 1 -index; }
+            BoardShift::Up => { position.y = board.height - 1 - index; }
+            BoardShift::Down => { position.y = index; }
+        }
+    }
+
+    // the cell `index` away from the edge the tiles slide to, in a row
+    fn position(&self, board: &Board, row: u8, index: u8) -> Position {
+        let mut position = match self {
+            BoardShift::Left | BoardShift::Right => Position { x: 0, y: row },
+            BoardShift::Up | BoardShift::Down => Position { x: row, y: 0 },
+        };
+        self.set_column_position(board, &mut position, index);
+        position
+    }
+
+    // the inverse of set_column_position, how far a position is from the edge the tiles slide to
+    fn get_column_position(&self, board: &Board, position: &Position) -> u8 {
+        match self {
+            BoardShift::Left => position.x,
+            BoardShift::Right => board.width - 1 - position.x,
+            BoardShift::Up => board.height - 1 - position.y,
+            BoardShift::Down => position.y,
+        }
+    }
+
+    // how many cells a row has in the direction of the move
+    fn line_length(&self, board: &Board) -> u8 {
+        match self {
+            BoardShift::Left | BoardShift::Right => board.width,
+            BoardShift::Up | BoardShift::Down => board.height,
+        }
+    }
+
+    fn get_row_position(&self, position: &Position) -> u8 {
+        match self {
+            BoardShift::Left | BoardShift::Right => position.y,
+            BoardShift::Up | BoardShift::Down => position.x,
+        }
+    }
+}
+
+// part 14
+
+// the tile that follows a move on a board
+struct NewTileEvent {
+    board: Entity,
+    area: SpawnArea,
+}
+
+// a tile or a wall that did come in, a full board or a used up puzzle queue has nothing to bring
+struct SpawnedEvent;
+
+// where that tile may come in
+enum SpawnArea {
+    Anywhere,
+    Edge { shift: BoardShift, rows: Vec<u8> },  // threes: the far end of a row that moved
+}
+
+fn new_tile_handler(
+    mut tile_reader: EventReader<NewTileEvent>,
+    mut spawned_writer: EventWriter<SpawnedEvent>,
+    mut commands: Commands,
+    mut boards: Query<(&Board, &Rules, &mut Progress)>,
+    tiles: Query<(&Position, &Parent)>,
+    font_spec: Res<FontSpec>,
+    mut rng: ResMut<GameRng>,
+    settings: Res<Settings>,
+) {
+    for event in tile_reader.iter() {  // one a board and frame at most, but iter will clear the queue
+        let Ok((board, rules, mut progress)) = boards.get_mut(event.board) else { continue; };
+        let free = free_cells(board, &event.area, |new_pos| tiles.iter().any(|(pos, parent)| parent.get() == event.board && pos == new_pos));
+
+        // a fixed sequence goes to the first free cell, reading from the top left
+        if let Some(queue) = progress.spawn_queue.as_mut() {
+            let Some(position) = free.min_by_key(|pos| (std::cmp::Reverse(pos.y), pos.x)) else { continue; };
+            if queue.is_empty() { continue; }
+            let value = queue.remove(0);
+            spawn_tile(&mut commands, event.board, board, &font_spec, &settings.theme.palette(), position, value);
+            spawned_writer.send(SpawnedEvent);
+            continue;
+        }
+
+        match random_spawn(free, rules, settings.wall_chance, &mut rng.0) {
+            Some(Spawn::Wall(position)) => spawn_wall(&mut commands, event.board, board, &settings.theme.palette(), position),
+            Some(Spawn::Tile(position, value)) => {
+                spawn_tile(&mut commands, event.board, board, &font_spec, &settings.theme.palette(), position, value);
+            }
+            None => continue,
+        }
+        spawned_writer.send(SpawnedEvent);
+    }
+}
+
+// the cells of the area nothing is on yet
+fn free_cells<'a>(board: &Board, area: &SpawnArea, occupied: impl Fn(&Position) -> bool + 'a) -> impl Iterator<Item = Position> + 'a {
+    let candidates: Vec<Position> = match area {
+        SpawnArea::Anywhere => board.cells().collect(),
+        SpawnArea::Edge { shift, rows } => rows.iter()
+            .map(|row| shift.position(board, *row, shift.line_length(board) - 1))
+            .filter(|pos| !board.dead.contains(pos))
+            .collect(),
+    };
+    candidates.into_iter().filter(move |pos| !occupied(pos))
+}
+
+enum Spawn {
+    Tile(Position, u32),
+    Wall(Position),  // the walls variant lets one in now and then
+}
+
+// what comes in after a move, nothing once the board is full
+fn random_spawn(free: impl Iterator<Item = Position>, rules: &Rules, wall_chance: f32, rng: &mut StdRng) -> Option<Spawn> {
+    let position = free.choose(rng)?;
+    if rules.variant == Variant::Walls && rng.gen_bool(wall_chance.into()) {
+        Some(Spawn::Wall(position))
+    } else {
+        Some(Spawn::Tile(position, rules.variant.merge_rule().new_tile_value(rng, rules.four_chance)))
+    }
+}
+
+// tiles and walls are children of their board, placed relative to it
+fn spawn_tile(commands: &mut Commands, board_entity: Entity, board: &Board, font_spec: &FontSpec, palette: &Palette, pos: Position, value: u32) {
+    let tile = commands.spawn(SpriteBundle {
+        sprite: Sprite {
+            color: palette.tile,
+            custom_size: Some(Vec2::new(TILE_SIZE, TILE_SIZE)),
+            ..default()
+        },
+        transform: Transform::from_translation(board.cell_position_to_physical(pos).extend(2.0)),
+        ..default()
+    })
+        .with_children(|child_builder| {
+            child_builder.spawn(Text2dBundle{
+                text: Text::from_section(
+                    value.to_string(),
+                    TextStyle {
+                        font: font_spec.family.clone(),
+                        font_size: 40.0,
+                        color: palette.tile_text,
+                    })
+                    .with_alignment(TextAlignment::Center),
+                transform: Transform::from_xyz(0.0, 0.0, 2.0),
+                ..default()
+            }).insert(TileText);
+        })
+        .insert(Points {value})
+        .insert(pos)
+        .id();
+    commands.entity(board_entity).add_child(tile);
+}
+
+fn spawn_wall(commands: &mut Commands, board_entity: Entity, board: &Board, palette: &Palette, pos: Position) {
+    let wall = commands.spawn(SpriteBundle {
+        sprite: Sprite {
+            color: palette.wall,
+            custom_size: Some(Vec2::new(TILE_SIZE + TILE_SPACER, TILE_SIZE + TILE_SPACER)),
+            ..default()
+        },
+        transform: Transform::from_translation(board.cell_position_to_physical(pos).extend(2.0)),
+        ..default()
+    })
+        .insert(Wall)
+        .insert(pos)
+        .id();
+    commands.entity(board_entity).add_child(wall);
+}
+
+// a board before its last few moves, newest last
+#[derive(Component, Default)]
+struct UndoHistory {
+    snapshots: Vec<Snapshot>,
+}
+
+struct Snapshot {
+    tiles: Vec<(Position, u32)>,
+    walls: Vec<Position>,
+    progress: Progress,
+}
+
+impl UndoHistory {
+    fn push(&mut self, snapshot: Snapshot, limit: u8) {
+        self.snapshots.push(snapshot);
+        let excess = self.snapshots.len().saturating_sub(limit.into());
+        self.snapshots.drain(..excess);
+    }
+}
+
+fn undo_move(
+    mut commands: Commands,
+    input: Res<Input<KeyCode>>,
+    tiles: Query<(Entity, &Parent), With<Position>>,
+    mut boards: Query<(Entity, &Board, &Controls, &mut Progress, &mut UndoHistory)>,
+    font_spec: Res<FontSpec>,
+    settings: Res<Settings>,
+) {
+    if !input.just_pressed(settings.key_bindings.undo) { return; }
+    // the undo key goes with the key bindings, it takes back the last move of every board they move
+    for (board_entity, board, controls, mut progress, mut history) in boards.iter_mut() {
+        if *controls != Controls::Bindings { continue; }
+        let Some(snapshot) = history.snapshots.pop() else { continue; };
+
+        for (entity, parent) in tiles.iter() {
+            if parent.get() == board_entity { commands.entity(entity).despawn_recursive(); }
+        }
+        for (position, value) in snapshot.tiles {
+            spawn_tile(&mut commands, board_entity, board, &font_spec, &settings.theme.palette(), position, value);
+        }
+        for position in snapshot.walls {
+            spawn_wall(&mut commands, board_entity, board, &settings.theme.palette(), position);
+        }
+        *progress = snapshot.progress;
+    }
+}
+
+// part 14
+
+// what all boards share, the score of each is in its Progress
+#[derive(Resource, Default)]
+struct Game {
+    score_best: u32,
+    elapsed: Duration,
+    in_progress: bool,  // from the starting tiles until the game over
+    mode: GameMode,
+}
+
+// what the next new game is, picked in the menus
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum GameMode {
+    #[default]
+    Endless,
+    Puzzle(usize),  // index into the loaded puzzles
+    Daily(i64),  // the day it is seeded from
+    TimeAttack,  // best score before the clock runs out
+    MoveLimit,  // best score in a fixed number of moves
+    Race,  // two players on one keyboard, first to the target tile or the best score when time is up
+    Compare,  // a board for every variant, all moved by the same keys
+    Versus,  // a race against another player over the network
+}
+
+const TIME_ATTACK_LIMIT: Duration = Duration::from_secs(3 * 60);
+const MOVE_LIMIT: u32 = 100;
+const RACE_TILE: u32 = 512;
+
+impl GameMode {
+    fn time_limit(&self) -> Option<Duration> {
+        if let GameMode::TimeAttack | GameMode::Race | GameMode::Versus = self { Some(TIME_ATTACK_LIMIT) } else { None }
+    }
+
+    fn move_limit(&self) -> Option<u32> {
+        if let GameMode::MoveLimit = self { Some(MOVE_LIMIT) } else { None }
+    }
+
+    // dealt from a seed someone else knows too: the day's, or the server's for both players
+    fn shares_seed(&self) -> bool {
+        matches!(self, GameMode::Daily(_) | GameMode::Versus)
+    }
+
+    // added to the variant on the leaderboard, a timed score isn't up against an endless one
+    fn leaderboard_name(&self) -> Option<&'static str> {
+        match self {
+            GameMode::TimeAttack => Some("timed"),
+            GameMode::MoveLimit => Some("100 moves"),
+            GameMode::Endless | GameMode::Puzzle(_) | GameMode::Daily(_) | GameMode::Race | GameMode::Compare | GameMode::Versus => None,
+        }
+    }
+}
+
+impl Game {
+    fn time_left(&self) -> Option<Duration> {
+        self.mode.time_limit().map(|limit| limit.saturating_sub(self.elapsed))
+    }
+
+    fn moves_left(&self, progress: &Progress) -> Option<u32> {
+        self.mode.move_limit().map(|limit| limit.saturating_sub(progress.moves))
+    }
+}
+
+fn no_game_in_progress(game: Res<Game>) -> bool {
+    !game.in_progress
+}
+
+fn finish_game(mut game: ResMut<Game>) {
+    game.in_progress = false;
+}
+
+// runs only while playing, and stands still while the window is in the background
+fn game_clock(time: Res<Time>, windows: Query<&Window>, mut game: ResMut<Game>) {
+    if windows.iter().len() > 0 && !windows.iter().any(|window| window.focused) { return; }
+    game.elapsed += time.delta();
+}
+
+// every random choice of the game goes through this one, so a seed replays the same game
+#[derive(Resource)]
+struct GameRng(StdRng);
+
+impl GameRng {
+    fn seeded(seed: u64) -> Self {
+        GameRng(StdRng::seed_from_u64(seed))
+    }
+
+    // deals from a seed other people know for one game, the own rng is set aside until the next
+    fn share(&mut self, seed: u64, own: &mut OwnRng) {
+        let previous = std::mem::replace(self, GameRng::seeded(seed));
+        own.0.get_or_insert(previous);
+    }
+}
+
+// the player's own rng while a daily or a versus deals from a shared seed. otherwise the games
+// after one would go on from a seed anyone can work out
+#[derive(Resource, Default)]
+struct OwnRng(Option<GameRng>);
+
+fn take_back_own_rng(game: Res<Game>, mut rng: ResMut<GameRng>, mut own: ResMut<OwnRng>) {
+    if game.mode.shares_seed() { return; }
+    if let Some(previous) = own.0.take() { *rng = previous; }
+}
+
+impl Default for GameRng {
+    fn default() -> Self {
+        GameRng(StdRng::from_entropy())
+    }
+}
+
+//part 18
+// over when every board is stuck, or when the limit of the mode runs out
+fn end_game(
+    tiles: Query<(&Position, &Points, &Parent)>,
+    walls: Query<(&Position, &Parent), (With<Wall>, Without<Points>)>,
+    boards: Query<(Entity, &Board, &Rules, &Progress)>,
+    game: Res<Game>,
+    mut state: ResMut<NextState<RunState>>
+) {
+    // the timed and move limited modes also end when their limit runs out
+    if game.time_left() == Some(Duration::ZERO) || boards.iter().any(|(_, _, _, progress)| game.moves_left(progress) == Some(0)) {
+        state.set(RunState::GameOver);
+        return;
+    }
+
+    // stuck is when no direction moves anything, a free cell walls or dead cells keep the tiles away from doesn't help
+    let all_stuck = boards.iter().all(|(entity, board, rules, _)| {
+        let tiles: Vec<(Position, u32)> = tiles.iter()
+            .filter(|(_, _, parent)| parent.get() == entity)
+            .map(|(position, points, _)| (*position, points.value))
+            .collect();
+        let walls: Vec<Position> = walls.iter()
+            .filter(|(_, parent)| parent.get() == entity)
+            .map(|(position, _)| *position)
+            .collect();
+        !gym::ACTIONS.iter().any(|shift| can_slide(board, &walls, rules.variant, *shift, &tiles))
+    });
+
+    if all_stuck {
+        state.set(RunState::GameOver);
+    }
+}
+
+
+//part 19
+#[derive(States, Debug, Clone, Copy, Default, Eq, PartialEq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunState {
+    #[default]
+    MainMenu,
+    Playing,
+    Paused,
+    Won,
+    GameOver,
+    Statistics,
+    Leaderboard,
+    Settings,
+    Puzzles,
+    Solved,
+    Daily,
+    Lobby,  // waiting on the server for an opponent
+}
+
+fn escape_key(
+    input: Res<Input<KeyCode>>,
+    run_state: Res<State<RunState>>,
+    mut next_state: ResMut<NextState<RunState>>,
+) {
+    if !input.just_pressed(KeyCode::Escape) { return; }
+
+    match run_state.0 {
+        RunState::Playing => { next_state.set(RunState::Paused); }
+        RunState::Paused | RunState::Won => { next_state.set(RunState::Playing); }
+        RunState::Statistics | RunState::Leaderboard | RunState::Settings | RunState::Puzzles | RunState::Daily | RunState::Lobby => {
+            next_state.set(RunState::MainMenu);
+        }
+        RunState::MainMenu | RunState::GameOver | RunState::Solved => {}
+    }
+}
+
+// the first time a game makes the winning tile; keep going is an option.
+// in a race the first board to the target tile wins it
+fn win_check(
+    tiles: Query<(&Points, &Parent)>,
+    mut boards: Query<(Entity, &mut Progress)>,
+    game: Res<Game>,
+    mut state: ResMut<NextState<RunState>>,
+) {
+    if let GameMode::Puzzle(_) = game.mode { return; }
+    let (target, next) = match game.mode {
+        GameMode::Race | GameMode::Versus => (RACE_TILE, RunState::GameOver),
+        _ => (WIN_TILE, RunState::Won),
+    };
+
+    for (entity, mut progress) in boards.iter_mut() {
+        if progress.won || !tiles.iter().any(|(points, parent)| parent.get() == entity && points.value >= target) { continue; }
+        progress.won = true;
+        state.set(next);
+    }
+}
+
+
+// part 21
+fn game_reset(
+    mut commands: Commands,
+    tiles: Query<Entity, With<Position>>,
+    mut boards: Query<(&mut Progress, &mut UndoHistory)>,
+    mut game: ResMut<Game>,
+) {
+    for entity in tiles.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    for (mut progress, mut history) in boards.iter_mut() {
+        *progress = Progress::default();
+        history.snapshots.clear();
+    }
+    game.elapsed = default();
+}
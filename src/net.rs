@@ -0,0 +1,248 @@
+// the online versus: connect to the server (src/bin/server.rs), wait in the lobby for an opponent,
+// then send the own board after every move and mirror theirs as it comes in
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs, Shutdown};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use bevy::prelude::*;
+use rand::prelude::*;
+use crate::{spawn_tile, spawn_wall, Board, Controls, FontSpec, Game, GameMode, GameRng, OwnRng, Points, Position, Progress, RunState, ScoreGainEvent, Wall};
+use crate::protocol::{Message, DEFAULT_ADDRESS};
+use crate::settings::Settings;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+// a merge making this much or more drops a blocker on the other board, when the server has them on
+pub const GARBAGE_TILE: u32 = 64;
+
+// where the server is, from BOXES_SERVER or else this machine
+#[derive(Resource)]
+pub struct ServerAddress(pub String);
+
+impl Default for ServerAddress {
+    fn default() -> Self {
+        ServerAddress(std::env::var("BOXES_SERVER").unwrap_or(DEFAULT_ADDRESS.to_string()))
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct Online {
+    pub connection: Option<Connection>,
+    pub garbage: bool,  // from the start message, the server decides for both players
+}
+
+// connecting, reads and writes happen on threads of their own, a slow network never holds up a frame
+pub struct Connection {
+    outgoing: Option<Sender<Message>>,  // only None while the connection is dropped
+    incoming: Mutex<Receiver<Message>>,
+    writer: Option<(JoinHandle<()>, Arc<AtomicBool>)>,  // the thread that connects and writes, and whether it has
+}
+
+impl Connection {
+    // a connection that doesn't come about reads as the opponent leaving
+    pub fn connect(address: &str) -> Connection {
+        let (outgoing, to_send) = channel::<Message>();
+        let (received, incoming) = channel();
+        let connected = Arc::new(AtomicBool::new(false));
+
+        let address = address.to_string();
+        let writer = thread::spawn({
+            let connected = connected.clone();
+            move || {
+                let (mut writer, reader) = match open(&address) {
+                    Ok(stream) => stream,
+                    Err(error) => {
+                        warn!("no versus server at {address}: {error}");
+                        return;
+                    }
+                };
+                connected.store(true, Ordering::Release);
+                thread::spawn(move || {
+                    for line in BufReader::new(reader).lines().map_while(Result::ok) {
+                        let Some(message) = Message::from_line(&line) else { continue; };
+                        if received.send(message).is_err() { break; }
+                    }
+                });
+                for message in to_send {
+                    if writer.write_all(message.to_line().as_bytes()).is_err() { break; }
+                }
+                // whatever was queued is out, the reading thread stops once the socket is shut
+                let _ = writer.shutdown(Shutdown::Both);
+            }
+        });
+        Connection { outgoing: Some(outgoing), incoming: Mutex::new(incoming), writer: Some((writer, connected)) }
+    }
+
+    // a connection without a server, the tests play the other end of the two channels
+    #[cfg(test)]
+    pub fn local() -> (Connection, Sender<Message>, Receiver<Message>) {
+        let (outgoing, sent) = channel();
+        let (to_game, incoming) = channel();
+        (Connection { outgoing: Some(outgoing), incoming: Mutex::new(incoming), writer: None }, to_game, sent)
+    }
+
+    pub fn send(&self, message: Message) {
+        // a closed line shows up on the receiving end
+        if let Some(outgoing) = &self.outgoing { let _ = outgoing.send(message); }
+    }
+
+    // everything that came in since the last frame. a closed line is as good as the opponent leaving
+    fn receive(&self) -> Vec<Message> {
+        let incoming = self.incoming.lock().expect("only ever locked for a moment");
+        let mut messages = Vec::new();
+        loop {
+            match incoming.try_recv() {
+                Ok(message) => messages.push(message),
+                Err(TryRecvError::Empty) => return messages,
+                Err(TryRecvError::Disconnected) => {
+                    messages.push(Message::Left);
+                    return messages;
+                }
+            }
+        }
+    }
+}
+
+// the writing thread stops once the sender is dropped, after sending what is still queued, the last
+// board of a game included. one still connecting has nothing to send and isn't waited for
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.outgoing = None;
+        if let Some((writer, connected)) = self.writer.take() {
+            if connected.load(Ordering::Acquire) { let _ = writer.join(); }
+        }
+    }
+}
+
+// the stream to write and a clone to read
+fn open(address: &str) -> io::Result<(TcpStream, TcpStream)> {
+    let address = address.to_socket_addrs()?.next().ok_or(io::ErrorKind::AddrNotAvailable)?;
+    let stream = TcpStream::connect_timeout(&address, CONNECT_TIMEOUT)?;
+    stream.set_write_timeout(Some(CONNECT_TIMEOUT))?;  // hanging up waits for the writes, never for long
+    let reader = stream.try_clone()?;
+    Ok((stream, reader))
+}
+
+pub fn playing_versus(game: Res<Game>) -> bool {
+    game.mode == GameMode::Versus
+}
+
+pub fn connect_to_server(mut online: ResMut<Online>, address: Res<ServerAddress>) {
+    online.connection = Some(Connection::connect(&address.0));
+}
+
+// the server starts the game once a second player turns up
+pub fn await_opponent(
+    mut online: ResMut<Online>,
+    mut rng: ResMut<GameRng>,
+    mut own: ResMut<OwnRng>,
+    mut game: ResMut<Game>,
+    mut next_state: ResMut<NextState<RunState>>,
+) {
+    let Some(connection) = &online.connection else { return; };
+    for message in connection.receive() {
+        match message {
+            Message::Start { seed, garbage } => {
+                rng.share(seed, &mut own);
+                online.garbage = garbage;
+                game.mode = GameMode::Versus;
+                game.in_progress = false;
+                next_state.set(RunState::Playing);
+                return;
+            }
+            Message::Left => {
+                online.connection = None;
+                return;
+            }
+            Message::Board { .. } | Message::Garbage { .. } => {}
+        }
+    }
+}
+
+pub fn receive_opponent(
+    mut commands: Commands,
+    online: Res<Online>,
+    mut boards: Query<(Entity, &Board, &Controls, &mut Progress)>,
+    cells: Query<(Entity, &Position, &Parent)>,
+    font_spec: Res<FontSpec>,
+    settings: Res<Settings>,
+    mut rng: ResMut<GameRng>,
+    mut next_state: ResMut<NextState<RunState>>,
+) {
+    let Some(connection) = &online.connection else { return; };
+    let palette = settings.theme.palette();
+    for message in connection.receive() {
+        match message {
+            Message::Board { tiles, walls, score, moves } => {
+                for (entity, board, controls, mut progress) in boards.iter_mut() {
+                    if *controls != Controls::Remote { continue; }
+                    for (cell, _, parent) in cells.iter() {
+                        if parent.get() == entity { commands.entity(cell).despawn_recursive(); }
+                    }
+                    for &(x, y, value) in &tiles {
+                        spawn_tile(&mut commands, entity, board, &font_spec, &palette, Position { x, y }, value);
+                    }
+                    for &(x, y) in &walls {
+                        spawn_wall(&mut commands, entity, board, &palette, Position { x, y });
+                    }
+                    progress.score = score;
+                    progress.moves = moves;
+                }
+            }
+            // blockers land on free cells of the own board, they stay for the rest of the game
+            Message::Garbage { count } => {
+                for (entity, board, controls, _) in boards.iter() {
+                    if *controls != Controls::Bindings { continue; }
+                    let free: Vec<Position> = board.cells()
+                        .filter(|pos| !cells.iter().any(|(_, cell, parent)| parent.get() == entity && cell == pos))
+                        .collect();
+                    for pos in free.choose_multiple(&mut rng.0, count as usize) {
+                        spawn_wall(&mut commands, entity, board, &palette, *pos);
+                    }
+                }
+            }
+            Message::Left => { next_state.set(RunState::GameOver); }
+            Message::Start { .. } => {}
+        }
+    }
+}
+
+// the whole own board whenever its tiles or its score change
+pub fn send_board(
+    online: Res<Online>,
+    boards: Query<(&Controls, &Progress, &Children), Or<(Changed<Progress>, Changed<Children>)>>,
+    tiles: Query<(&Position, &Points)>,
+    walls: Query<&Position, With<Wall>>,
+) {
+    let Some(connection) = &online.connection else { return; };
+    for (controls, progress, children) in boards.iter() {
+        if *controls != Controls::Bindings { continue; }
+        connection.send(Message::Board {
+            tiles: tiles.iter_many(children).map(|(pos, points)| (pos.x, pos.y, points.value)).collect(),
+            walls: walls.iter_many(children).map(|pos| (pos.x, pos.y)).collect(),
+            score: progress.score,
+            moves: progress.moves,
+        });
+    }
+}
+
+pub fn send_garbage(online: Res<Online>, mut gain_reader: EventReader<ScoreGainEvent>, boards: Query<&Controls>) {
+    let Some(connection) = online.connection.as_ref().filter(|_| online.garbage) else {
+        gain_reader.clear();
+        return;
+    };
+    for gain in gain_reader.iter() {
+        if boards.get(gain.board) != Ok(&Controls::Bindings) { continue; }
+        let count = gain.merges.iter().filter(|(_, value)| *value >= GARBAGE_TILE).count() as u32;
+        if count > 0 { connection.send(Message::Garbage { count }); }
+    }
+}
+
+// leaving or finishing a versus hangs up, the server tells the other player
+pub fn hang_up(mut online: ResMut<Online>, mut game: ResMut<Game>) {
+    if online.connection.take().is_some() && game.mode == GameMode::Versus {
+        game.in_progress = false;  // no carrying on without the opponent
+    }
+}
@@ -0,0 +1,49 @@
+// what goes over the wire between the game and the versus server, one json object a line.
+// the game and src/bin/server.rs both speak it
+
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_ADDRESS: &str = "127.0.0.1:2048";
+// the board both players deal from the seed, part of the protocol as much as the messages are
+pub const VERSUS_SIZE: u8 = 4;
+pub const VERSUS_FOUR_CHANCE: f32 = 0.1;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Message {
+    // server to both players once two are paired up, they deal from the same seed
+    Start { seed: u64, garbage: bool },
+    // player to the other player after every change, the whole board so nothing drifts apart
+    Board { tiles: Vec<(u8, u8, u32)>, walls: Vec<(u8, u8)>, score: u32, moves: u32 },
+    // blockers for the other board, one for every big merge when the server has them on
+    Garbage { count: u32 },
+    // server to whoever is left when the other player is gone
+    Left,
+}
+
+impl Message {
+    pub fn to_line(&self) -> String {
+        let mut line = serde_json::to_string(self).expect("messages always serialize");
+        line.push('\n');
+        line
+    }
+
+    pub fn from_line(line: &str) -> Option<Message> {
+        serde_json::from_str(line.trim()).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_are_tagged_json_lines() {
+        let start = Message::Start { seed: 7, garbage: true };
+        assert_eq!(start.to_line(), "{\"type\":\"start\",\"seed\":7,\"garbage\":true}\n");
+        let board = Message::Board { tiles: vec![(0, 1, 2)], walls: vec![(3, 3)], score: 4, moves: 1 };
+        assert_eq!(Message::from_line(&board.to_line()), Some(board));
+        assert_eq!(Message::from_line("{\"type\":\"left\"}"), Some(Message::Left));
+        assert_eq!(Message::from_line("not json"), None);
+    }
+}
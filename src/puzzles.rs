@@ -0,0 +1,166 @@
+use std::collections::BTreeMap;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::{Game, GameMode, Points, Position, Progress, RunState};
+use crate::levels::{read_level_file, Drawing};
+use crate::settings::Variant;
+use crate::storage::{Profile, Storage};
+
+const PUZZLES_FILE: &str = "puzzles";
+const RECORDS_FILE: &str = "puzzles";
+
+
+// a hand made starting position, its drawing has a number for each tile and '#' for each wall
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct Puzzle {
+    pub name: String,
+    pub rows: Drawing,
+    #[serde(default)]
+    pub variant: Variant,
+    pub spawns: Vec<u32>,  // the values that come in after each move, in order; nothing once they run out
+    pub goal: Goal,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Goal {
+    Reach { tile: u32, moves: Option<u32> },
+    SingleTile { moves: Option<u32> },
+}
+
+impl Goal {
+    pub fn moves(&self) -> Option<u32> {
+        match self {
+            Goal::Reach { moves, .. } | Goal::SingleTile { moves } => *moves,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        let target = match self {
+            Goal::Reach { tile, .. } => format!("make {tile}"),
+            Goal::SingleTile { .. } => "clear to a single tile".to_string(),
+        };
+        match self.moves() {
+            Some(moves) => format!("{target} in {moves} moves"),
+            None => target,
+        }
+    }
+
+    fn reached(&self, tiles: &[u32]) -> bool {
+        match self {
+            Goal::Reach { tile, .. } => tiles.iter().any(|value| value >= tile),
+            Goal::SingleTile { .. } => tiles.len() == 1,
+        }
+    }
+}
+
+impl Puzzle {
+    pub fn tiles(&self) -> Vec<(Position, u32)> {
+        self.rows.cells().filter_map(|(pos, token)| Some((pos, token.parse().ok()?))).collect()
+    }
+
+    pub fn walls(&self) -> Vec<Position> {
+        self.rows.cells().filter(|(_, token)| *token == "#").map(|(pos, _)| pos).collect()
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct Puzzles(pub Vec<Puzzle>);
+
+pub fn load_puzzles(mut puzzles: ResMut<Puzzles>) {
+    puzzles.0 = read_level_file(PUZZLES_FILE);
+}
+
+// the fewest moves each solved puzzle took, by puzzle name
+#[derive(Resource, Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct PuzzleRecords {
+    pub best_moves: BTreeMap<String, u32>,
+}
+
+impl PuzzleRecords {
+    pub fn record(&mut self, name: &str, moves: u32) {
+        let best = self.best_moves.entry(name.to_string()).or_insert(moves);
+        *best = (*best).min(moves);
+    }
+}
+
+pub fn load_puzzle_records(mut records: ResMut<PuzzleRecords>, storage: Res<Storage>, profile: Res<Profile>) {
+    *records = storage.load(&profile.file(RECORDS_FILE));
+}
+
+pub fn playing_puzzle(game: Res<Game>) -> bool {
+    matches!(game.mode, GameMode::Puzzle(_))
+}
+
+// solved as soon as the goal shows on the board, lost once the moves for it are used up
+pub fn puzzle_check(
+    tiles: Query<&Points>,
+    boards: Query<&Progress>,
+    game: Res<Game>,
+    puzzles: Res<Puzzles>,
+    mut state: ResMut<NextState<RunState>>,
+) {
+    let GameMode::Puzzle(index) = game.mode else { return; };
+    let Some(puzzle) = puzzles.0.get(index) else { return; };
+    let Ok(progress) = boards.get_single() else { return; };
+
+    let values: Vec<u32> = tiles.iter().map(|points| points.value).collect();
+    if puzzle.goal.reached(&values) {
+        state.set(RunState::Solved);
+    } else if puzzle.goal.moves().is_some_and(|moves| progress.moves >= moves) {
+        state.set(RunState::GameOver);
+    }
+}
+
+pub fn record_solved_puzzle(
+    game: Res<Game>,
+    boards: Query<&Progress>,
+    puzzles: Res<Puzzles>,
+    mut records: ResMut<PuzzleRecords>,
+    storage: Res<Storage>,
+    profile: Res<Profile>,
+) {
+    let GameMode::Puzzle(index) = game.mode else { return; };
+    let Some(puzzle) = puzzles.0.get(index) else { return; };
+    let Ok(progress) = boards.get_single() else { return; };
+    records.record(&puzzle.name, progress.moves);
+    storage.save(&profile.file(RECORDS_FILE), &*records);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use super::*;
+
+    #[test]
+    fn grid_tokens_become_tiles_walls_and_dead_cells() {
+        let puzzle = Puzzle {
+            name: "test".to_string(),
+            rows: Drawing(vec!["x 2 #".to_string(), "16 . .".to_string()]),
+            variant: Variant::Classic,
+            spawns: Vec::new(),
+            goal: Goal::SingleTile { moves: None },
+        };
+        assert_eq!(puzzle.rows.size(), (3, 2));
+        assert_eq!(puzzle.tiles(), vec![(Position { x: 1, y: 1 }, 2), (Position { x: 0, y: 0 }, 16)]);
+        assert_eq!(puzzle.walls(), vec![Position { x: 2, y: 1 }]);
+        assert_eq!(puzzle.rows.dead_cells(), HashSet::from([Position { x: 0, y: 1 }]));
+    }
+
+    #[test]
+    fn shipped_puzzles_load() {
+        let puzzles: Vec<Puzzle> = read_level_file(PUZZLES_FILE);
+        assert!(puzzles.len() >= 4);
+        assert!(puzzles.iter().all(|puzzle| !puzzle.tiles().is_empty()));
+    }
+
+    #[test]
+    fn records_keep_the_fewest_moves() {
+        let mut records = PuzzleRecords::default();
+        records.record("a", 7);
+        records.record("a", 5);
+        records.record("a", 9);
+        assert_eq!(records.best_moves["a"], 5);
+    }
+}
@@ -0,0 +1,97 @@
+use rand::prelude::*;
+
+// which neighbouring tiles merge and what comes in after a move; board_shift and end_game ask this
+pub trait MergeRule: Send + Sync {
+    fn merges(&self, a: u32, b: u32) -> bool;
+
+    fn merged(&self, a: u32, b: u32) -> u32 {
+        a + b
+    }
+
+    // `bigger_chance` is the settings' chance of a 4 in the classic rules
+    fn new_tile_value(&self, rng: &mut dyn RngCore, bigger_chance: f32) -> u32;
+}
+
+// 2 + 2 = 4, 4 + 4 = 8, ...
+pub struct Doubling;
+
+impl MergeRule for Doubling {
+    fn merges(&self, a: u32, b: u32) -> bool {
+        a == b
+    }
+
+    fn new_tile_value(&self, rng: &mut dyn RngCore, bigger_chance: f32) -> u32 {
+        if rng.gen_bool(bigger_chance.into()) { 4 } else { 2 }
+    }
+}
+
+// neighbours in the sequence merge: 1 + 1 = 2, 1 + 2 = 3, 2 + 3 = 5, 3 + 5 = 8, ...
+pub struct Fibonacci;
+
+impl MergeRule for Fibonacci {
+    fn merges(&self, a: u32, b: u32) -> bool {
+        let (small, big) = (a.min(b), a.max(b));
+        // only 1 + 1 may repeat a number, every other pair is two consecutive fibonacci numbers
+        (small == 1 && big <= 2) || (small > 1 && is_fibonacci(small) && next_fibonacci(small) == big)
+    }
+
+    fn new_tile_value(&self, rng: &mut dyn RngCore, bigger_chance: f32) -> u32 {
+        if rng.gen_bool(bigger_chance.into()) { 2 } else { 1 }
+    }
+}
+
+fn is_fibonacci(value: u32) -> bool {
+    let (mut a, mut b) = (1, 2);
+    while a < value {
+        (a, b) = (b, a + b);
+    }
+    a == value
+}
+
+fn next_fibonacci(value: u32) -> u32 {
+    let (mut a, mut b) = (1, 2);
+    while a < value {
+        (a, b) = (b, a + b);
+    }
+    b
+}
+
+// as in threes: a 1 only merges with a 2, from 3 on equal values merge
+pub struct Threes;
+
+impl MergeRule for Threes {
+    fn merges(&self, a: u32, b: u32) -> bool {
+        a + b == 3 || (a == b && a >= 3)
+    }
+
+    fn new_tile_value(&self, rng: &mut dyn RngCore, bigger_chance: f32) -> u32 {
+        if rng.gen_bool(bigger_chance.into()) { 3 } else if rng.gen_bool(0.5) { 2 } else { 1 }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fibonacci_merges_neighbours_in_the_sequence() {
+        assert!(Fibonacci.merges(1, 1));
+        assert!(Fibonacci.merges(2, 1));
+        assert!(Fibonacci.merges(3, 5));
+        assert!(Fibonacci.merges(89, 55));
+        assert!(!Fibonacci.merges(2, 2));
+        assert!(!Fibonacci.merges(3, 8));
+        assert!(!Fibonacci.merges(4, 6), "not fibonacci numbers at all");
+        assert_eq!(Fibonacci.merged(34, 55), 89);
+    }
+
+    #[test]
+    fn threes_needs_a_one_and_a_two_to_start() {
+        assert!(Threes.merges(1, 2));
+        assert!(!Threes.merges(1, 1));
+        assert!(!Threes.merges(2, 2));
+        assert!(Threes.merges(3, 3));
+        assert!(Threes.merges(48, 48));
+        assert!(!Threes.merges(3, 6));
+    }
+}
@@ -0,0 +1,345 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::BoardShift;
+use crate::rules::{Doubling, Fibonacci, MergeRule, Threes};
+use crate::storage::Storage;
+
+pub const SETTINGS_FILE: &str = "settings";
+pub const BOARD_SIZES: std::ops::RangeInclusive<u8> = 3..=8;
+pub const MAX_UNDO_LIMIT: u8 = 10;
+
+
+// everything the player can change at runtime; systems read it every frame so changes apply at once
+#[derive(Resource, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct Settings {
+    pub board_width: u8,
+    pub board_height: u8,
+    pub shape: Option<String>,  // a board shape from the level file instead of the width and height
+    pub four_chance: f32,  // probability of a new tile being a 4 instead of a 2
+    pub animation_speed: f32,
+    pub theme: Theme,
+    pub key_bindings: KeyBindings,
+    pub master_volume: f32,
+    pub effects_volume: f32,
+    pub music_volume: f32,
+    pub muted: bool,
+    pub undo_limit: u8,
+    pub variant: Variant,  // takes effect with the next new game
+    pub wall_chance: f32,  // walls variant: probability of a wall coming in instead of a tile
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            board_width: 4,
+            board_height: 4,
+            shape: None,
+            four_chance: 0.1,
+            animation_speed: 1.0,
+            theme: Theme::Light,
+            key_bindings: KeyBindings::default(),
+            master_volume: 0.8,
+            effects_volume: 1.0,
+            music_volume: 0.5,
+            muted: false,
+            undo_limit: 3,
+            variant: Variant::Classic,
+            wall_chance: 0.0,
+        }
+    }
+}
+
+// the rules a game is played by, leaderboard entries are kept apart per variant
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Variant {
+    #[default]
+    Classic,
+    Walls,  // a few immovable walls split the rows
+    Fibonacci,
+    Threes,  // tiles move one cell a move and new cards come in from the far edge
+}
+
+impl Variant {
+    pub const ALL: [Variant; 4] = [Variant::Classic, Variant::Walls, Variant::Fibonacci, Variant::Threes];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Variant::Classic => "classic",
+            Variant::Walls => "walls",
+            Variant::Fibonacci => "fibonacci",
+            Variant::Threes => "threes",
+        }
+    }
+
+    pub fn merge_rule(&self) -> &'static dyn MergeRule {
+        match self {
+            Variant::Classic | Variant::Walls => &Doubling,
+            Variant::Fibonacci => &Fibonacci,
+            Variant::Threes => &Threes,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Theme {
+    Light,
+    Dark,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Palette {
+    pub background: Color,
+    pub board: Color,
+    pub placeholder: Color,
+    pub tile: Color,
+    pub tile_text: Color,
+    pub wall: Color,
+}
+
+impl Theme {
+    pub fn palette(&self) -> Palette {
+        match self {
+            Theme::Light => Palette {
+                background: ClearColor::default().0,
+                board: crate::BOARD_COLOR,
+                placeholder: crate::TILE_PLACEHOLDER_COLOR,
+                tile: crate::TILE_COLOR,
+                tile_text: Color::BLACK,
+                wall: Color::rgb(0.35, 0.35, 0.4),
+            },
+            Theme::Dark => Palette {
+                background: Color::rgb(0.08, 0.08, 0.1),
+                board: Color::rgb(0.2, 0.2, 0.25),
+                placeholder: Color::rgb(0.26, 0.26, 0.32),
+                tile: Color::rgb(0.85, 0.6, 0.3),
+                tile_text: Color::WHITE,
+                wall: Color::rgb(0.05, 0.05, 0.06),
+            },
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(default)]
+pub struct KeyBindings {
+    pub left: KeyCode,
+    pub right: KeyCode,
+    pub up: KeyCode,
+    pub down: KeyCode,
+    pub undo: KeyCode,
+    pub mute: KeyCode,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        KeyBindings {
+            left: KeyCode::Left,
+            right: KeyCode::Right,
+            up: KeyCode::Up,
+            down: KeyCode::Down,
+            undo: KeyCode::U,
+            mute: KeyCode::M,
+        }
+    }
+}
+
+impl KeyBindings {
+    pub fn shift_for(&self, key: KeyCode) -> Option<BoardShift> {
+        [
+            (self.left, BoardShift::Left),
+            (self.right, BoardShift::Right),
+            (self.up, BoardShift::Up),
+            (self.down, BoardShift::Down),
+        ].into_iter().find_map(|(bound, shift)| (bound == key).then_some(shift))
+    }
+
+    pub fn key(&self, action: KeyAction) -> KeyCode {
+        let mut bindings = *self;
+        *bindings.key_mut(action)
+    }
+
+    pub fn key_mut(&mut self, action: KeyAction) -> &mut KeyCode {
+        match action {
+            KeyAction::Left => &mut self.left,
+            KeyAction::Right => &mut self.right,
+            KeyAction::Up => &mut self.up,
+            KeyAction::Down => &mut self.down,
+            KeyAction::Undo => &mut self.undo,
+            KeyAction::Mute => &mut self.mute,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyAction {
+    Left,
+    Right,
+    Up,
+    Down,
+    Undo,
+    Mute,
+}
+
+impl KeyAction {
+    pub const ALL: [KeyAction; 6] = [
+        KeyAction::Left,
+        KeyAction::Right,
+        KeyAction::Up,
+        KeyAction::Down,
+        KeyAction::Undo,
+        KeyAction::Mute,
+    ];
+}
+
+// the settings screen steps through values of these with "<" and ">"
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SettingField {
+    BoardWidth,
+    BoardHeight,
+    FourChance,
+    AnimationSpeed,
+    Theme,
+    MasterVolume,
+    EffectsVolume,
+    MusicVolume,
+    UndoLimit,
+    Variant,
+    WallChance,
+}
+
+impl SettingField {
+    pub const ALL: [SettingField; 11] = [
+        SettingField::BoardWidth,
+        SettingField::BoardHeight,
+        SettingField::FourChance,
+        SettingField::AnimationSpeed,
+        SettingField::Theme,
+        SettingField::MasterVolume,
+        SettingField::EffectsVolume,
+        SettingField::MusicVolume,
+        SettingField::UndoLimit,
+        SettingField::Variant,
+        SettingField::WallChance,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            SettingField::BoardWidth => "Board width",
+            SettingField::BoardHeight => "Board height",
+            SettingField::FourChance => "Chance of 4",
+            SettingField::AnimationSpeed => "Animation speed",
+            SettingField::Theme => "Theme",
+            SettingField::MasterVolume => "Volume",
+            SettingField::EffectsVolume => "Effects",
+            SettingField::MusicVolume => "Music",
+            SettingField::UndoLimit => "Undo limit",
+            SettingField::Variant => "Variant",
+            SettingField::WallChance => "Wall spawns",
+        }
+    }
+}
+
+impl Settings {
+    pub fn adjust(&mut self, field: SettingField, step: i8) {
+        let step_f = f32::from(step);
+        match field {
+            SettingField::BoardWidth => { self.board_width = step_board_size(self.board_width, step); }
+            SettingField::BoardHeight => { self.board_height = step_board_size(self.board_height, step); }
+            SettingField::FourChance => { self.four_chance = round_tenth(self.four_chance + 0.1 * step_f).clamp(0.0, 1.0); }
+            SettingField::AnimationSpeed => { self.animation_speed = round_tenth(self.animation_speed + 0.5 * step_f).clamp(0.5, 4.0); }
+            SettingField::Theme => {
+                self.theme = match self.theme {
+                    Theme::Light => Theme::Dark,
+                    Theme::Dark => Theme::Light,
+                };
+            }
+            SettingField::MasterVolume => { self.master_volume = step_volume(self.master_volume, step_f); }
+            SettingField::EffectsVolume => { self.effects_volume = step_volume(self.effects_volume, step_f); }
+            SettingField::MusicVolume => { self.music_volume = step_volume(self.music_volume, step_f); }
+            SettingField::UndoLimit => { self.undo_limit = self.undo_limit.saturating_add_signed(step).min(MAX_UNDO_LIMIT); }
+            SettingField::Variant => {
+                let index = Variant::ALL.iter().position(|variant| *variant == self.variant).unwrap_or(0);
+                let count = Variant::ALL.len() as isize;
+                self.variant = Variant::ALL[(index as isize + isize::from(step)).rem_euclid(count) as usize];
+            }
+            SettingField::WallChance => { self.wall_chance = round_hundredth(self.wall_chance + 0.05 * step_f).clamp(0.0, 0.3); }
+        }
+    }
+
+    pub fn describe(&self, field: SettingField) -> String {
+        match field {
+            SettingField::BoardWidth => self.board_width.to_string(),
+            SettingField::BoardHeight => self.board_height.to_string(),
+            SettingField::FourChance => format!("{:.0}%", self.four_chance * 100.0),
+            SettingField::AnimationSpeed => format!("{:.1}x", self.animation_speed),
+            SettingField::Theme => format!("{:?}", self.theme),
+            SettingField::MasterVolume if self.muted => "Muted".to_string(),
+            SettingField::MasterVolume => format!("{:.0}%", self.master_volume * 100.0),
+            SettingField::EffectsVolume => format!("{:.0}%", self.effects_volume * 100.0),
+            SettingField::MusicVolume => format!("{:.0}%", self.music_volume * 100.0),
+            SettingField::UndoLimit => self.undo_limit.to_string(),
+            SettingField::Variant => format!("{:?}", self.variant),
+            SettingField::WallChance => format!("{:.0}%", self.wall_chance * 100.0),
+        }
+    }
+
+    // what sounds actually play at, after the master volume and the mute key
+    pub fn effects_level(&self) -> f32 {
+        if self.muted { 0.0 } else { self.master_volume * self.effects_volume }
+    }
+
+    pub fn music_level(&self) -> f32 {
+        if self.muted { 0.0 } else { self.master_volume * self.music_volume }
+    }
+}
+
+fn step_board_size(size: u8, step: i8) -> u8 {
+    size.saturating_add_signed(step).clamp(*BOARD_SIZES.start(), *BOARD_SIZES.end())
+}
+
+fn step_volume(volume: f32, step: f32) -> f32 {
+    round_tenth(volume + 0.1 * step).clamp(0.0, 1.0)
+}
+
+// the steps are tenths, keep them from drifting
+fn round_tenth(value: f32) -> f32 {
+    (value * 10.0).round() / 10.0
+}
+
+fn round_hundredth(value: f32) -> f32 {
+    (value * 100.0).round() / 100.0
+}
+
+pub fn load_settings(mut settings: ResMut<Settings>, storage: Res<Storage>) {
+    *settings = storage.load(SETTINGS_FILE);
+}
+
+pub fn save_settings(settings: Res<Settings>, storage: Res<Storage>) {
+    storage.save(SETTINGS_FILE, &*settings);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adjustments_stay_in_range() {
+        let mut settings = Settings::default();
+        for _ in 0..20 {
+            settings.adjust(SettingField::BoardWidth, 1);
+            settings.adjust(SettingField::FourChance, -1);
+            settings.adjust(SettingField::UndoLimit, 1);
+        }
+        assert_eq!(settings.board_width, *BOARD_SIZES.end());
+        assert_eq!(settings.board_height, 4);
+        assert_eq!(settings.four_chance, 0.0);
+        assert_eq!(settings.undo_limit, MAX_UNDO_LIMIT);
+
+        settings.adjust(SettingField::MasterVolume, -3);
+        assert_eq!(settings.describe(SettingField::MasterVolume), "50%");
+        settings.muted = true;
+        assert_eq!(settings.describe(SettingField::MasterVolume), "Muted");
+        assert_eq!(settings.effects_level(), 0.0);
+    }
+}
@@ -0,0 +1,188 @@
+// spectators: with BOXES_SPECTATE=127.0.0.1:2049 set, the game serves a websocket on that address
+// and publishes json text frames to everyone connected, for a browser page or a stream overlay.
+// spectators only listen, whatever they send is ignored. the messages, all with a "type":
+//
+//   {"type":"state","state":"playing","mode":"endless"}
+//      the screen the game is on (main_menu, playing, paused, won, game_over, ...) and the mode,
+//      modes with a value come as an object: {"daily":20744}, {"puzzle":2}
+//   {"type":"board","board":0,"name":"You","width":4,"height":4,"tiles":[[0,0,2]],"walls":[[3,3]],"score":0,"moves":0}
+//      the whole of a board whenever it changes, tiles as [x, y, value] with y = 0 the bottom row.
+//      board is its place in the layout, boards of several player modes each come on their own
+//   {"type":"move","board":0,"direction":"left","gain":4,"merges":[[0,0,4]]}
+//      a move that slid something, the points it won and the tiles its merges made
+//
+// someone joining halfway first gets the latest state and board messages, then everything as it happens
+use std::collections::BTreeMap;
+use std::io;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::mpsc::{channel, sync_channel, Sender, SyncSender};
+use std::thread;
+use std::time::Duration;
+use bevy::prelude::*;
+use serde::Serialize;
+use tungstenite::{accept, Message, WebSocket};
+use crate::{BoardIndex, BoardShift, Board, Controls, Game, GameMode, MoveEvent, Points, Position, Progress, Rules, RunState, ScoreGainEvent, Wall};
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Broadcast {
+    State { state: RunState, mode: GameMode },
+    Board {
+        board: usize,
+        name: &'static str,
+        width: u8,
+        height: u8,
+        tiles: Vec<(u8, u8, u32)>,
+        walls: Vec<(u8, u8)>,
+        score: u32,
+        moves: u32,
+    },
+    Move { board: usize, direction: BoardShift, gain: u32, merges: Vec<(u8, u8, u32)> },
+}
+
+impl Broadcast {
+    // what a late spectator needs to catch up: the state and the last of every board
+    fn latest_key(&self) -> Option<String> {
+        match self {
+            Broadcast::State { .. } => Some("state".to_string()),
+            Broadcast::Board { board, .. } => Some(format!("board {board}")),
+            Broadcast::Move { .. } => None,
+        }
+    }
+}
+
+// a spectator this slow to take a handshake or a message is dropped
+const SPECTATOR_TIMEOUT: Duration = Duration::from_secs(2);
+// messages waiting for one spectator, one falling further behind is dropped
+const SPECTATOR_BACKLOG: usize = 256;
+
+enum Feed {
+    Join(WebSocket<TcpStream>),
+    Publish(Broadcast),
+}
+
+// the listener has a thread of its own, and so does every spectator for its handshake and sending,
+// a slow one doesn't hold up the others
+#[derive(Resource)]
+pub struct Spectators {
+    feed: Sender<Feed>,
+    pub address: SocketAddr,  // the one actually bound, port 0 picks a free one
+}
+
+impl Spectators {
+    pub fn listen(address: &str) -> io::Result<Spectators> {
+        let listener = TcpListener::bind(address)?;
+        let address = listener.local_addr()?;
+        let (feed, updates) = channel();
+
+        let joins = feed.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().map_while(Result::ok) {
+                let joins = joins.clone();
+                thread::spawn(move || {
+                    // a failed handshake is someone who wasn't a websocket client to begin with
+                    let Some(socket) = handshake(stream) else { return; };
+                    let _ = joins.send(Feed::Join(socket));
+                });
+            }
+        });
+        thread::spawn(move || {
+            let mut spectators: Vec<SyncSender<String>> = Vec::new();
+            let mut latest: BTreeMap<String, String> = BTreeMap::new();
+            for update in updates {
+                match update {
+                    Feed::Join(socket) => {
+                        let spectator = watch(socket);
+                        if latest.values().all(|text| spectator.try_send(text.clone()).is_ok()) {
+                            spectators.push(spectator);
+                        }
+                    }
+                    Feed::Publish(broadcast) => {
+                        let text = serde_json::to_string(&broadcast).expect("broadcasts always serialize");
+                        if let Some(key) = broadcast.latest_key() { latest.insert(key, text.clone()); }
+                        spectators.retain(|spectator| spectator.try_send(text.clone()).is_ok());
+                    }
+                }
+            }
+        });
+        Ok(Spectators { feed, address })
+    }
+
+    pub fn publish(&self, broadcast: Broadcast) {
+        let _ = self.feed.send(Feed::Publish(broadcast));
+    }
+}
+
+fn handshake(stream: TcpStream) -> Option<WebSocket<TcpStream>> {
+    stream.set_read_timeout(Some(SPECTATOR_TIMEOUT)).ok()?;
+    stream.set_write_timeout(Some(SPECTATOR_TIMEOUT)).ok()?;
+    accept(stream).ok()
+}
+
+// sends one spectator its messages until it goes or times out, which closes the channel for the publisher
+fn watch(mut socket: WebSocket<TcpStream>) -> SyncSender<String> {
+    let (spectator, messages) = sync_channel::<String>(SPECTATOR_BACKLOG);
+    thread::spawn(move || {
+        for text in messages {
+            if socket.send(Message::Text(text)).is_err() { break; }
+        }
+    });
+    spectator
+}
+
+pub fn start_spectating(mut commands: Commands) {
+    let Ok(address) = std::env::var("BOXES_SPECTATE") else { return; };
+    match Spectators::listen(&address) {
+        Ok(spectators) => {
+            info!("spectators can connect to ws://{}", spectators.address);
+            commands.insert_resource(spectators);
+        }
+        Err(error) => warn!("no spectating on {address}: {error}"),
+    }
+}
+
+pub fn publish_state(spectators: Res<Spectators>, state: Res<State<RunState>>, game: Res<Game>) {
+    spectators.publish(Broadcast::State { state: state.0, mode: game.mode });
+}
+
+// every board whose tiles or progress changed this frame
+pub fn publish_boards(
+    spectators: Res<Spectators>,
+    game: Res<Game>,
+    boards: Query<(&BoardIndex, &Board, &Controls, &Rules, &Progress, &Children), Or<(Changed<Progress>, Changed<Children>)>>,
+    tiles: Query<(&Position, &Points)>,
+    walls: Query<&Position, With<Wall>>,
+) {
+    for (index, board, controls, rules, progress, children) in boards.iter() {
+        spectators.publish(Broadcast::Board {
+            board: index.0,
+            name: controls.board_name(rules, game.mode),
+            width: board.width,
+            height: board.height,
+            tiles: tiles.iter_many(children).map(|(pos, points)| (pos.x, pos.y, points.value)).collect(),
+            walls: walls.iter_many(children).map(|pos| (pos.x, pos.y)).collect(),
+            score: progress.score,
+            moves: progress.moves,
+        });
+    }
+}
+
+pub fn publish_moves(
+    spectators: Res<Spectators>,
+    mut move_reader: EventReader<MoveEvent>,
+    mut gain_reader: EventReader<ScoreGainEvent>,
+    boards: Query<&BoardIndex>,
+) {
+    let gains: Vec<&ScoreGainEvent> = gain_reader.iter().collect();
+    for event in move_reader.iter() {
+        let MoveEvent::Slid { board, shift } = event else { continue; };
+        let Ok(index) = boards.get(*board) else { continue; };
+        let gain = gains.iter().find(|gain| gain.board == *board);
+        spectators.publish(Broadcast::Move {
+            board: index.0,
+            direction: *shift,
+            gain: gain.map_or(0, |gain| gain.total),
+            merges: gain.map_or(Vec::new(), |gain| gain.merges.iter().map(|(pos, value)| (pos.x, pos.y, *value)).collect()),
+        });
+    }
+}
@@ -0,0 +1,122 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::{Game, Points, Progress};
+use crate::storage::{Profile, Storage};
+
+pub const WIN_TILE: u32 = 2048;
+const STATISTICS_FILE: &str = "statistics";
+
+
+// lifetime numbers of a profile, updated every time a game ends
+#[derive(Resource, Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct Statistics {
+    pub games_played: u32,
+    pub wins: u32,
+    pub scores: Vec<u32>,
+    pub best_tiles: BTreeMap<u32, u32>,  // best tile of a game -> how many games ended with it
+    pub total_moves: u64,
+    pub time_played: Duration,
+    pub current_streak: u32,
+    pub longest_streak: u32,
+}
+
+impl Statistics {
+    // a win is a game that reached the target of its rules, whatever the best tile was
+    pub fn record(&mut self, score: u32, best_tile: u32, moves: u32, time: Duration, won: bool) {
+        self.games_played += 1;
+        self.scores.push(score);
+        *self.best_tiles.entry(best_tile).or_default() += 1;
+        self.total_moves += u64::from(moves);
+        self.time_played += time;
+
+        if won {
+            self.wins += 1;
+            self.current_streak += 1;
+            self.longest_streak = self.longest_streak.max(self.current_streak);
+        } else {
+            self.current_streak = 0;
+        }
+    }
+
+    pub fn average_score(&self) -> u32 {
+        if self.scores.is_empty() { return 0; }
+        let total: u64 = self.scores.iter().map(|score| u64::from(*score)).sum();
+        (total / self.scores.len() as u64) as u32
+    }
+
+    pub fn median_score(&self) -> u32 {
+        let mut scores = self.scores.clone();
+        scores.sort_unstable();
+        match scores.len() {
+            0 => 0,
+            len if len % 2 == 0 => ((u64::from(scores[len / 2 - 1]) + u64::from(scores[len / 2])) / 2) as u32,
+            len => scores[len / 2],
+        }
+    }
+}
+
+pub fn load_statistics(
+    mut statistics: ResMut<Statistics>,
+    storage: Res<Storage>,
+    profile: Res<Profile>,
+) {
+    *statistics = storage.load(&profile.file(STATISTICS_FILE));
+}
+
+// a game of one, a race isn't anyone's statistics
+pub fn record_statistics(
+    game: Res<Game>,
+    boards: Query<&Progress>,
+    tiles: Query<&Points>,
+    mut statistics: ResMut<Statistics>,
+    storage: Res<Storage>,
+    profile: Res<Profile>,
+) {
+    let Ok(progress) = boards.get_single() else { return; };
+    let best_tile = tiles.iter().map(|points| points.value).max().unwrap_or(0);
+    statistics.record(progress.score, best_tile, progress.moves, game.elapsed, progress.won);
+    storage.save(&profile.file(STATISTICS_FILE), &*statistics);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn median_of_even_and_odd_counts() {
+        let mut statistics = Statistics::default();
+        assert_eq!(statistics.median_score(), 0);
+
+        for score in [300, 100, 200] {
+            statistics.record(score, 128, 10, Duration::ZERO, false);
+        }
+        assert_eq!(statistics.median_score(), 200);
+
+        statistics.record(1000, 128, 10, Duration::ZERO, false);
+        assert_eq!(statistics.median_score(), 250);
+        assert_eq!(statistics.average_score(), 400);
+    }
+
+    #[test]
+    fn median_of_huge_scores_doesnt_overflow() {
+        let mut statistics = Statistics::default();
+        statistics.record(u32::MAX, 128, 10, Duration::ZERO, false);
+        statistics.record(u32::MAX - 2, 128, 10, Duration::ZERO, false);
+        assert_eq!(statistics.median_score(), u32::MAX - 1);
+    }
+
+    #[test]
+    fn a_loss_breaks_the_streak() {
+        let mut statistics = Statistics::default();
+        for best_tile in [2048, 4096, 512, 2048] {
+            statistics.record(0, best_tile, 0, Duration::ZERO, best_tile >= 2048);
+        }
+        assert_eq!(statistics.wins, 3);
+        assert_eq!(statistics.longest_streak, 2);
+        assert_eq!(statistics.current_streak, 1);
+        assert_eq!(statistics.best_tiles.get(&2048), Some(&2));
+    }
+}
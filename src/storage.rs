@@ -0,0 +1,120 @@
+use std::fs;
+use std::path::PathBuf;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
+
+const PROFILE_FILE: &str = "profile";
+
+
+// everything the game remembers between sessions, one json file per thing
+#[derive(Resource)]
+pub struct Storage {
+    dir: Option<PathBuf>,
+}
+
+impl Default for Storage {
+    fn default() -> Self {
+        let dir = std::env::var_os("BOXES_DATA_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from("saves"));
+        Storage { dir: Some(dir) }
+    }
+}
+
+impl Storage {
+    // nothing is read or written, every load gives a default
+    pub fn in_memory() -> Self {
+        Storage { dir: None }
+    }
+
+    pub fn load<T: DeserializeOwned + Default>(&self, name: &str) -> T {
+        let Some(path) = self.path(name) else { return T::default(); };
+        let Ok(content) = fs::read_to_string(&path) else { return T::default(); };
+        serde_json::from_str(&content).unwrap_or_else(|err| {
+            warn!("{} is unreadable, starting from scratch: {err}", path.display());
+            T::default()
+        })
+    }
+
+    pub fn save<T: Serialize>(&self, name: &str, value: &T) {
+        let Some(path) = self.path(name) else { return; };
+        let content = serde_json::to_string_pretty(value).expect("saved values are plain data");
+        let result = match path.parent() {
+            Some(parent) => fs::create_dir_all(parent),
+            None => Ok(()),
+        }.and_then(|_| fs::write(&path, content));
+
+        if let Err(err) = result {
+            warn!("could not save {}: {err}", path.display());
+        }
+    }
+
+    // plain text for people rather than the game, next to the saves
+    pub fn export(&self, name: &str, text: &str) -> Option<PathBuf> {
+        let path = self.dir.as_ref()?.join(format!("{name}.txt"));
+        let result = match path.parent() {
+            Some(parent) => fs::create_dir_all(parent),
+            None => Ok(()),
+        }.and_then(|_| fs::write(&path, text));
+
+        match result {
+            Ok(()) => Some(path),
+            Err(err) => {
+                warn!("could not write {}: {err}", path.display());
+                None
+            }
+        }
+    }
+
+    fn path(&self, name: &str) -> Option<PathBuf> {
+        self.dir.as_ref().map(|dir| dir.join(format!("{name}.json")))
+    }
+}
+
+// whose statistics and progress are being kept, picked on the settings screen from the names on the leaderboard
+#[derive(Resource, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct Profile {
+    pub name: String,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Profile { name: "player".to_string() }
+    }
+}
+
+impl Profile {
+    // a name is whatever was typed, only letters, digits, spaces, - and _ make it into the path
+    pub fn file(&self, name: &str) -> String {
+        let dir: String = self.name.chars()
+            .map(|char| if char.is_alphanumeric() || matches!(char, ' ' | '-' | '_') { char } else { '_' })
+            .collect();
+        format!("profiles/{dir}/{name}")
+    }
+}
+
+pub fn load_profile(mut profile: ResMut<Profile>, storage: Res<Storage>) {
+    *profile = storage.load(PROFILE_FILE);
+}
+
+pub fn save_profile(profile: Res<Profile>, storage: Res<Storage>) {
+    storage.save(PROFILE_FILE, &*profile);
+}
+
+// another profile was picked, as opposed to the one loaded at startup
+pub fn profile_switched(profile: Res<Profile>) -> bool {
+    profile.is_changed() && !profile.is_added()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profile_names_stay_inside_the_profiles() {
+        let profile = Profile { name: "../Bob 2".to_string() };
+        assert_eq!(profile.file("statistics"), "profiles/___Bob 2/statistics");
+    }
+}
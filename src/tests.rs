@@ -0,0 +1,1243 @@
+// drives the game systems frame by frame, without a window, with a fixed seed
+
+use bevy::prelude::*;
+use crate::*;
+use crate::protocol::Message;
+
+fn test_app(seed: u64) -> App {
+    let mut app = headless_app(seed);
+    app.world.resource_mut::<NextState<RunState>>().set(RunState::Playing);
+    app.update();  // startup, main menu and straight into OnEnter(RunState::Playing)
+    app
+}
+
+// a game in `mode` on top of the saved settings as `settings` leaves them, past its first frame
+fn game_app(seed: u64, mode: GameMode, settings: impl FnOnce(&mut Settings)) -> App {
+    let mut app = headless_app(seed);
+    app.update();  // startup loads the saved settings
+    settings(&mut app.world.resource_mut::<Settings>());
+    app.world.resource_mut::<Game>().mode = mode;
+    app.world.resource_mut::<NextState<RunState>>().set(RunState::Playing);
+    app.update();
+    app
+}
+
+fn board_entity(app: &mut App) -> Entity {
+    app.world.query_filtered::<Entity, With<Board>>().single(&app.world)
+}
+
+fn progress(app: &mut App) -> Progress {
+    app.world.query::<&Progress>().single(&app.world).clone()
+}
+
+fn progress_mut(app: &mut App) -> Mut<'_, Progress> {
+    app.world.query::<&mut Progress>().single_mut(&mut app.world)
+}
+
+// replaces whatever spawn_tiles placed with the given (x, y, value) tiles
+fn set_tiles(app: &mut App, tiles: &[(u8, u8, u32)]) {
+    let board = board_entity(app);
+    set_board_tiles(app, board, tiles);
+}
+
+fn set_board_tiles(app: &mut App, board: Entity, tiles: &[(u8, u8, u32)]) {
+    let existing: Vec<Entity> = app.world.query_filtered::<(Entity, &Parent), With<Position>>()
+        .iter(&app.world)
+        .filter(|(_, parent)| parent.get() == board)
+        .map(|(entity, _)| entity)
+        .collect();
+    for entity in existing {
+        despawn_with_children_recursive(&mut app.world, entity);
+    }
+    for (x, y, value) in tiles {
+        let tile = app.world.spawn((
+            Position { x: *x, y: *y },
+            Points { value: *value },
+            TransformBundle::default(),
+        )).id();
+        app.world.entity_mut(board).add_child(tile);
+    }
+    app.update();  // render_tiles picks up the fresh tiles before any move touches them
+}
+
+fn add_walls(app: &mut App, walls: &[(u8, u8)]) {
+    let board = board_entity(app);
+    for (x, y) in walls {
+        let wall = app.world.spawn((Position { x: *x, y: *y }, Wall, TransformBundle::default())).id();
+        app.world.entity_mut(board).add_child(wall);
+    }
+}
+
+fn tiles(app: &mut App) -> Vec<(u8, u8, u32)> {
+    app.world.query::<(&Position, &Points)>()
+        .iter(&app.world)
+        .map(|(pos, points)| (pos.x, pos.y, points.value))
+        .sorted()
+        .collect()
+}
+
+fn press(app: &mut App, key: KeyCode) {
+    app.world.resource_mut::<Input<KeyCode>>().press(key);
+    app.update();
+    let mut input = app.world.resource_mut::<Input<KeyCode>>();
+    input.release(key);
+    input.clear();
+}
+
+fn run_state(app: &App) -> RunState {
+    app.world.resource::<State<RunState>>().0
+}
+
+#[test]
+fn starts_with_two_tiles() {
+    let mut app = test_app(1);
+
+    let tiles = tiles(&mut app);
+    assert_eq!(tiles.len(), 2);
+    assert!(tiles.iter().all(|(_, _, value)| *value == 2));
+    assert_eq!(progress(&mut app).score, 0);
+    assert_eq!(run_state(&app), RunState::Playing);
+}
+
+#[test]
+fn same_seed_same_game() {
+    let mut first = test_app(42);
+    let mut second = test_app(42);
+    assert_eq!(tiles(&mut first), tiles(&mut second));
+
+    for key in [KeyCode::Left, KeyCode::Up, KeyCode::Right, KeyCode::Down] {
+        press(&mut first, key);
+        press(&mut second, key);
+        assert_eq!(tiles(&mut first), tiles(&mut second));
+    }
+}
+
+#[test]
+fn merge_adds_score_and_spawns_tile() {
+    let mut app = test_app(7);
+    set_tiles(&mut app, &[(2, 0, 2), (3, 0, 2)]);
+
+    press(&mut app, KeyCode::Left);
+
+    let tiles = tiles(&mut app);
+    assert_eq!(tiles.len(), 2, "merged tile plus a new one: {tiles:?}");
+    assert!(tiles.contains(&(0, 0, 4)));
+    assert_eq!(progress(&mut app).score, 4);
+    assert_eq!(app.world.resource::<Game>().score_best, 4);
+
+    let gains = app.world.resource::<Events<ScoreGainEvent>>();
+    let mut reader = gains.get_reader();
+    let gain = reader.iter(gains).last().expect("a merge reports its gain");
+    assert_eq!(gain.total, 4);
+    assert_eq!(gain.merges, vec![(Position { x: 0, y: 0 }, 4)]);
+}
+
+#[test]
+fn different_values_slide_without_merging() {
+    let mut app = test_app(3);
+    set_tiles(&mut app, &[(0, 1, 2), (1, 1, 4), (0, 2, 8)]);
+
+    press(&mut app, KeyCode::Right);
+
+    let tiles = tiles(&mut app);
+    assert_eq!(tiles.len(), 4);
+    for tile in [(2, 1, 2), (3, 1, 4), (3, 2, 8)] {
+        assert!(tiles.contains(&tile), "{tile:?} expected in {tiles:?}");
+    }
+    assert_eq!(progress(&mut app).score, 0);
+}
+
+#[test]
+fn full_board_without_merges_is_game_over() {
+    let mut app = test_app(5);
+    let checkerboard: Vec<(u8, u8, u32)> = (0..4).cartesian_product(0..4)
+        .map(|(x, y)| (x, y, if (x + y) % 2 == 0 { 2 } else { 4 }))
+        .collect();
+    set_tiles(&mut app, &checkerboard);
+    app.update();  // applies the state transition
+
+    assert_eq!(run_state(&app), RunState::GameOver);
+}
+
+#[test]
+fn full_board_with_a_merge_keeps_playing() {
+    let mut app = test_app(5);
+    let board: Vec<(u8, u8, u32)> = (0..4).cartesian_product(0..4)
+        .map(|(x, y)| (x, y, if (x, y) == (0, 1) || (x + y) % 2 == 0 { 2 } else { 4 }))
+        .collect();
+    set_tiles(&mut app, &board);
+    app.update();
+
+    assert_eq!(run_state(&app), RunState::Playing);
+}
+
+#[test]
+fn merged_cell_is_free_for_the_new_tile_on_the_same_frame() {
+    // the merged tile leaves (1, 0), which has to be one of the cells the new tile can land on
+    let landed_on_freed_cell = (0..32).any(|seed| {
+        let mut app = test_app(seed);
+        set_tiles(&mut app, &[(0, 0, 2), (1, 0, 2), (0, 1, 8), (1, 1, 16)]);
+
+        press(&mut app, KeyCode::Left);
+
+        let tiles = tiles(&mut app);
+        assert_eq!(tiles.len(), 4, "merge removed one tile, spawn added one: {tiles:?}");
+        assert!(tiles.contains(&(0, 0, 4)));
+        tiles.iter().any(|(x, y, _)| (*x, *y) == (1, 0))
+    });
+    assert!(landed_on_freed_cell);
+}
+
+#[test]
+fn blocked_move_still_counts_with_a_sound_of_its_own() {
+    let mut app = test_app(61);
+    set_tiles(&mut app, &[(0, 0, 2), (0, 1, 4)]);
+
+    press(&mut app, KeyCode::Left);
+
+    let tiles = tiles(&mut app);
+    assert!(tiles.contains(&(0, 0, 2)) && tiles.contains(&(0, 1, 4)), "nothing slid");
+    assert_eq!(tiles.len(), 3, "but a new tile came in");
+    assert_eq!(progress(&mut app).moves, 1);
+    let events = app.world.resource::<Events<MoveEvent>>();
+    let mut reader = events.get_reader();
+    let blocked: Vec<bool> = reader.iter(events).map(|event| matches!(event, MoveEvent::Blocked { .. })).collect();
+    assert_eq!(blocked, vec![true]);
+}
+
+#[test]
+fn only_tiles_that_came_in_count_as_spawned() {
+    let mut app = test_app(61);
+    // a full board, nothing merges sideways and every column merges downwards
+    let columns: Vec<(u8, u8, u32)> = (0..4).cartesian_product(0..4)
+        .map(|(x, y)| (x, y, if x % 2 == 0 { 2 } else { 4 }))
+        .collect();
+    set_tiles(&mut app, &columns);
+    let spawned = |app: &App| app.world.resource::<Events<SpawnedEvent>>().iter_current_update_events().count();
+
+    press(&mut app, KeyCode::Left);
+    assert_eq!(progress(&mut app).moves, 1);
+    assert_eq!(spawned(&app), 0, "no room for a new tile");
+    press(&mut app, KeyCode::Down);
+    assert_eq!(spawned(&app), 1);
+}
+
+#[test]
+fn game_over_is_detected_on_the_frame_of_the_last_spawn() {
+    let mut app = test_app(13);
+    // nothing can move left, the only free cell gets the new tile, and the board is locked
+    let checkerboard: Vec<(u8, u8, u32)> = (0..4).cartesian_product(0..4)
+        .filter(|pos| *pos != (3, 3))
+        .map(|(x, y)| (x, y, if (x + y) % 2 == 0 { 2 } else { 4 }))
+        .collect();
+    set_tiles(&mut app, &checkerboard);
+
+    press(&mut app, KeyCode::Left);
+
+    assert_eq!(tiles(&mut app).len(), 16);
+    assert_eq!(app.world.resource::<NextState<RunState>>().0, Some(RunState::GameOver));
+}
+
+#[test]
+fn game_over_records_statistics() {
+    let mut app = test_app(17);
+    press(&mut app, KeyCode::Up);
+    let checkerboard: Vec<(u8, u8, u32)> = (0..4).cartesian_product(0..4)
+        .map(|(x, y)| (x, y, if (x + y) % 2 == 0 { 2 } else { 64 }))
+        .collect();
+    set_tiles(&mut app, &checkerboard);
+    app.update();
+
+    assert_eq!(run_state(&app), RunState::GameOver);
+    let statistics = app.world.resource::<Statistics>();
+    assert_eq!(statistics.games_played, 1);
+    assert_eq!(statistics.wins, 0);
+    assert_eq!(statistics.total_moves, 1);
+    assert_eq!(statistics.best_tiles.get(&64), Some(&1));
+}
+
+#[test]
+fn a_game_that_made_its_target_counts_as_a_win() {
+    let mut app = test_app(17);
+    set_tiles(&mut app, &[(0, 0, 1024), (1, 0, 1024)]);
+    press(&mut app, KeyCode::Left);
+    app.update();
+    assert_eq!(run_state(&app), RunState::Won);
+    app.world.resource_mut::<NextState<RunState>>().set(RunState::Playing);
+    app.update();
+
+    // kept going and lost the 2048 again
+    let checkerboard: Vec<(u8, u8, u32)> = (0..4).cartesian_product(0..4)
+        .map(|(x, y)| (x, y, if (x + y) % 2 == 0 { 2 } else { 64 }))
+        .collect();
+    set_tiles(&mut app, &checkerboard);
+    app.update();
+    assert_eq!(run_state(&app), RunState::GameOver);
+    assert_eq!(app.world.resource::<Statistics>().wins, 1);
+}
+
+#[test]
+fn high_score_waits_for_a_name_then_lands_on_the_leaderboard() {
+    let mut app = test_app(23);
+    set_tiles(&mut app, &[(0, 0, 2), (1, 0, 2)]);
+    press(&mut app, KeyCode::Left);
+    let score = progress(&mut app).score;
+
+    app.world.resource_mut::<NextState<RunState>>().set(RunState::GameOver);
+    app.update();
+    let pending = app.world.resource::<PendingEntry>().0.clone().expect("first game always qualifies");
+    assert_eq!((pending.score, pending.max_tile, pending.board(), pending.moves), (score, 4, (4, 4), 1));
+
+    app.world.resource_mut::<NextState<RunState>>().set(RunState::Playing);
+    app.update();
+    assert!(app.world.resource::<PendingEntry>().0.is_none());
+    let leaderboard = app.world.resource::<Leaderboard>();
+    assert_eq!(leaderboard.entries.len(), 1);
+    assert_eq!(leaderboard.entries[0].score, score);
+}
+
+#[test]
+fn typing_a_name_replaces_the_profile_name() {
+    let mut app = headless_app(23);
+    app.add_event::<bevy::window::ReceivedCharacter>()
+        .init_resource::<UiScale>()
+        .add_plugin(GameUiPlugin);
+    app.world.resource_mut::<NextState<RunState>>().set(RunState::Playing);
+    app.update();
+    set_tiles(&mut app, &[(0, 0, 2), (1, 0, 2)]);
+    press(&mut app, KeyCode::Left);
+    app.world.resource_mut::<NextState<RunState>>().set(RunState::GameOver);
+    app.update();
+    let profile_name = app.world.resource::<Profile>().name.clone();
+    assert_eq!(app.world.resource::<PendingEntry>().0.as_ref().unwrap().name, profile_name);
+
+    for char in "Bob".chars() {
+        app.world.send_event(bevy::window::ReceivedCharacter { window: Entity::PLACEHOLDER, char });
+    }
+    app.update();
+    assert_eq!(app.world.resource::<PendingEntry>().0.as_ref().unwrap().name, "Bob");
+    press(&mut app, KeyCode::Return);
+    assert_eq!(app.world.resource::<Leaderboard>().entries[0].name, "Bob");
+}
+
+#[test]
+fn a_name_from_the_leaderboard_can_be_picked_as_the_profile() {
+    let mut app = headless_app(23);
+    app.add_event::<bevy::window::ReceivedCharacter>()
+        .init_resource::<UiScale>()
+        .add_plugin(GameUiPlugin);
+    app.world.resource_mut::<NextState<RunState>>().set(RunState::Playing);
+    app.update();
+    set_tiles(&mut app, &[(0, 0, 2), (1, 0, 2)]);
+    press(&mut app, KeyCode::Left);
+    app.world.resource_mut::<NextState<RunState>>().set(RunState::GameOver);
+    app.update();
+    assert_eq!(app.world.resource::<Statistics>().games_played, 1);
+    for char in "Bob".chars() {
+        app.world.send_event(bevy::window::ReceivedCharacter { window: Entity::PLACEHOLDER, char });
+    }
+    app.update();
+    press(&mut app, KeyCode::Return);
+
+    app.world.resource_mut::<NextState<RunState>>().set(RunState::Settings);
+    app.update();
+    let button = app.world.query::<(Entity, &ButtonAction)>()
+        .iter(&app.world)
+        .find_map(|(entity, action)| (*action == ButtonAction::CycleProfile).then_some(entity))
+        .unwrap();
+    app.world.entity_mut(button).insert(Interaction::Clicked);
+    app.update();
+    app.update();
+    assert_eq!(app.world.resource::<Profile>().name, "Bob");
+    assert_eq!(app.world.resource::<Statistics>().games_played, 0, "Bob hasn't played yet");
+}
+
+#[test]
+fn ui_runs_alongside_the_game() {
+    let mut app = headless_app(19);
+    app.add_event::<bevy::window::ReceivedCharacter>()
+        .init_resource::<UiScale>()
+        .add_plugin(GameUiPlugin);
+    app.update();
+    assert_eq!(run_state(&app), RunState::MainMenu);
+    assert_eq!(app.world.query::<&StateScreen>().iter(&app.world).count(), 1);
+
+    app.world.resource_mut::<NextState<RunState>>().set(RunState::Playing);
+    app.update();
+    assert_eq!(app.world.query::<&StateScreen>().iter(&app.world).count(), 0, "the menu is gone");
+
+    set_tiles(&mut app, &[(0, 0, 2), (0, 1, 2)]);
+    press(&mut app, KeyCode::Down);
+    app.update();
+
+    assert_eq!(app.world.query::<&Popup>().iter(&app.world).count(), 2, "one over the score, one over the merge");
+    let expected = progress(&mut app).score.to_string();
+    let score = app.world.query_filtered::<&Text, With<ScoreDisplay>>().single(&app.world);
+    assert_eq!(score.sections[0].value, expected);
+    let moves = app.world.query_filtered::<&Text, With<MovesDisplay>>().single(&app.world);
+    assert_eq!(moves.sections[0].value, "1");
+
+    press(&mut app, KeyCode::Escape);
+    app.update();
+    assert_eq!(run_state(&app), RunState::Paused);
+    assert_eq!(app.world.query::<&StateScreen>().iter(&app.world).count(), 1);
+}
+
+#[test]
+fn clock_stops_when_the_game_is_over() {
+    let mut app = test_app(29);
+    app.update();
+    assert!(app.world.resource::<Game>().elapsed > std::time::Duration::ZERO);
+
+    app.world.resource_mut::<NextState<RunState>>().set(RunState::GameOver);
+    app.update();
+    let stopped_at = app.world.resource::<Game>().elapsed;
+    app.update();
+    app.update();
+    assert_eq!(app.world.resource::<Game>().elapsed, stopped_at);
+}
+
+#[test]
+fn pause_freezes_the_board_and_resumes_the_same_game() {
+    let mut app = test_app(31);
+    press(&mut app, KeyCode::Escape);
+    app.update();
+    assert_eq!(run_state(&app), RunState::Paused);
+    let before = tiles(&mut app);
+
+    for key in [KeyCode::Left, KeyCode::Up, KeyCode::Right, KeyCode::Down] {
+        press(&mut app, key);
+    }
+    assert_eq!(tiles(&mut app), before);
+
+    press(&mut app, KeyCode::Escape);
+    app.update();
+    assert_eq!(run_state(&app), RunState::Playing);
+    assert_eq!(tiles(&mut app), before, "resuming does not start over");
+}
+
+#[test]
+fn making_2048_offers_to_keep_going() {
+    let mut app = test_app(37);
+    set_tiles(&mut app, &[(0, 0, 1024), (1, 0, 1024)]);
+    press(&mut app, KeyCode::Left);
+    app.update();
+    assert_eq!(run_state(&app), RunState::Won);
+
+    app.world.resource_mut::<NextState<RunState>>().set(RunState::Playing);
+    app.update();
+    assert_eq!(run_state(&app), RunState::Playing);
+    assert!(tiles(&mut app).contains(&(0, 0, 2048)));
+
+    set_tiles(&mut app, &[(0, 0, 2048), (1, 0, 2), (2, 0, 2)]);
+    press(&mut app, KeyCode::Left);
+    app.update();
+    assert_eq!(run_state(&app), RunState::Playing, "only the first 2048 stops the game");
+}
+
+#[test]
+fn new_tiles_follow_the_spawn_probability() {
+    let mut app = game_app(41, GameMode::Endless, |settings| settings.four_chance = 1.0);
+    assert!(tiles(&mut app).iter().all(|(_, _, value)| *value == 4));
+
+    press(&mut app, KeyCode::Left);
+    assert!(tiles(&mut app).iter().all(|(_, _, value)| *value == 4 || *value == 8));
+}
+
+#[test]
+fn rebound_keys_move_the_board() {
+    let mut app = test_app(43);
+    app.world.resource_mut::<Settings>().key_bindings.left = KeyCode::A;
+    set_tiles(&mut app, &[(3, 2, 2)]);
+
+    press(&mut app, KeyCode::Left);
+    assert!(tiles(&mut app).contains(&(3, 2, 2)), "arrows are not bound anymore");
+
+    press(&mut app, KeyCode::A);
+    assert!(tiles(&mut app).contains(&(0, 2, 2)));
+}
+
+#[test]
+fn undo_restores_board_and_score_up_to_the_limit() {
+    let mut app = test_app(47);
+    app.world.resource_mut::<Settings>().undo_limit = 1;
+    set_tiles(&mut app, &[(0, 0, 2), (1, 0, 2)]);
+    let start = tiles(&mut app);
+
+    press(&mut app, KeyCode::Left);
+    press(&mut app, KeyCode::Right);
+    assert_eq!(progress(&mut app).score, 4);
+    let after_first_move = progress(&mut app).moves - 1;
+
+    press(&mut app, KeyCode::U);
+    app.update();
+    assert_eq!(progress(&mut app).moves, after_first_move);
+    assert_eq!(progress(&mut app).score, 4);
+    assert_eq!(tiles(&mut app).len(), 2, "back to after the merge");
+
+    press(&mut app, KeyCode::U);
+    app.update();
+    assert_ne!(tiles(&mut app), start, "only one move is remembered");
+}
+
+#[test]
+fn changing_the_board_size_starts_over_on_a_new_board() {
+    let mut app = test_app(53);
+    press(&mut app, KeyCode::Up);
+    app.world.resource_mut::<Settings>().board_width = 6;
+    app.world.resource_mut::<NextState<RunState>>().set(RunState::MainMenu);
+    app.update();
+    app.world.resource_mut::<NextState<RunState>>().set(RunState::Playing);
+    app.update();
+
+    let board = app.world.query::<&Board>().single(&app.world);
+    assert_eq!((board.width, board.height), (6, 4));
+    assert_eq!(tiles(&mut app).len(), 2);
+    assert_eq!(progress(&mut app).moves, 0);
+}
+
+#[test]
+fn board_scales_with_the_window() {
+    let mut app = headless_app(59);
+    app.add_event::<bevy::window::ReceivedCharacter>()
+        .init_resource::<UiScale>()
+        .add_plugin(GameUiPlugin);
+    let window = app.world.spawn((Window::default(), bevy::window::PrimaryWindow)).id();
+    app.world.spawn(Camera2dBundle::default());
+    app.update();
+
+    let projected = |app: &mut App| {
+        let board = app.world.query::<&Board>().single(&app.world).physical_size;
+        let scale = app.world.query::<&OrthographicProjection>().single(&app.world).scale;
+        board.y / scale
+    };
+    let small = projected(&mut app);
+    assert!(small > 600.0 && small < 720.0, "fills most of the 720px high window, got {small}");
+
+    app.world.get_mut::<Window>(window).unwrap().resolution.set(2560.0, 1440.0);
+    app.update();
+    assert_eq!(projected(&mut app), small * 2.0);
+    assert_eq!(app.world.resource::<UiScale>().scale, 2.0);
+}
+
+#[test]
+fn walls_split_rows_into_segments() {
+    let mut app = test_app(67);
+    set_tiles(&mut app, &[(0, 0, 2), (2, 0, 2), (3, 0, 2), (0, 1, 2), (3, 1, 2), (3, 2, 8)]);
+    add_walls(&mut app, &[(1, 0), (1, 1)]);
+
+    press(&mut app, KeyCode::Left);
+
+    let after = tiles(&mut app);
+    assert!(after.contains(&(0, 0, 2)) && after.contains(&(2, 0, 4)), "merges within a segment only");
+    assert!(after.contains(&(0, 1, 2)) && after.contains(&(2, 1, 2)), "stops at the wall without merging across");
+    assert!(after.contains(&(0, 2, 8)), "rows without walls slide all the way");
+    assert_eq!(progress(&mut app).score, 4);
+}
+
+#[test]
+fn walls_variant_starts_with_walls() {
+    let mut app = game_app(71, GameMode::Endless, |settings| settings.variant = Variant::Walls);
+
+    let walls: Vec<Position> = app.world.query_filtered::<&Position, With<Wall>>().iter(&app.world).copied().collect();
+    assert_eq!(walls.len(), 2);
+    assert_eq!(tiles(&mut app).len(), 2);
+    assert!(tiles(&mut app).iter().all(|(x, y, _)| !walls.contains(&Position { x: *x, y: *y })));
+}
+
+#[test]
+fn walls_fill_the_board_for_game_over() {
+    let mut app = test_app(73);
+    set_tiles(&mut app, &[(0, 0, 2), (1, 0, 4)]);
+    let walls: Vec<(u8, u8)> = (0..4).cartesian_product(0..4).filter(|cell| !matches!(cell, (0, 0) | (1, 0))).collect();
+    add_walls(&mut app, &walls);
+    app.update();
+
+    assert_eq!(app.world.resource::<NextState<RunState>>().0, Some(RunState::GameOver));
+}
+
+#[test]
+fn a_free_cell_walled_in_doesnt_keep_the_game_going() {
+    let mut app = test_app(73);
+    // nothing merges, and no tile can get past the walls into the corner
+    let checkerboard: Vec<(u8, u8, u32)> = (0..4).cartesian_product(0..4)
+        .filter(|cell| !matches!(cell, (0, 0) | (1, 0) | (0, 1)))
+        .map(|(x, y)| (x, y, if (x + y) % 2 == 0 { 2 } else { 4 }))
+        .collect();
+    set_tiles(&mut app, &checkerboard);
+    add_walls(&mut app, &[(1, 0), (0, 1)]);
+    app.update();
+
+    assert_eq!(app.world.resource::<NextState<RunState>>().0, Some(RunState::GameOver));
+}
+
+#[test]
+fn fibonacci_variant_merges_neighbours_in_the_sequence() {
+    let mut app = game_app(79, GameMode::Endless, |settings| settings.variant = Variant::Fibonacci);
+    assert!(tiles(&mut app).iter().all(|(_, _, value)| *value <= 2), "starts with 1s and 2s");
+
+    set_tiles(&mut app, &[(0, 0, 1), (1, 0, 2), (2, 0, 2), (0, 1, 5), (1, 1, 3)]);
+    press(&mut app, KeyCode::Left);
+    let after = tiles(&mut app);
+    assert!(after.contains(&(0, 0, 3)) && after.contains(&(1, 0, 2)));
+    assert!(after.contains(&(0, 1, 8)));
+    assert_eq!(progress(&mut app).score, 11);
+
+    // equal values never merge, so a board full of 2s is over
+    let twos: Vec<(u8, u8, u32)> = (0..4).cartesian_product(0..4).map(|(x, y)| (x, y, 2)).collect();
+    set_tiles(&mut app, &twos);
+    assert_eq!(app.world.resource::<NextState<RunState>>().0, Some(RunState::GameOver));
+}
+
+#[test]
+fn threes_moves_one_cell_and_deals_from_the_far_edge() {
+    let mut app = game_app(83, GameMode::Endless, |settings| settings.variant = Variant::Threes);
+    assert_eq!(tiles(&mut app).len(), 8, "half the board is dealt");
+
+    set_tiles(&mut app, &[(0, 0, 1), (1, 0, 2), (3, 0, 3), (0, 1, 3), (1, 1, 3), (2, 1, 3), (2, 2, 6)]);
+    press(&mut app, KeyCode::Left);
+
+    let after = tiles(&mut app);
+    for tile in [(0, 0, 3), (2, 0, 3), (0, 1, 6), (1, 1, 3), (1, 2, 6)] {
+        assert!(after.contains(&tile), "{tile:?} missing from {after:?}");
+    }
+    let dealt: Vec<_> = after.iter().filter(|(x, _, _)| *x == 3).collect();
+    assert_eq!(dealt.len(), 1);
+    assert!(dealt[0].1 < 3, "only rows that moved get a new card");
+    assert_eq!(progress(&mut app).score, 9);
+}
+
+#[test]
+fn rectangular_boards_slide_to_their_own_edges() {
+    let mut app = game_app(89, GameMode::Endless, |settings| {
+        settings.board_width = 5;
+        settings.board_height = 3;
+    });
+    assert!(tiles(&mut app).iter().all(|(x, y, _)| *x < 5 && *y < 3));
+
+    for (key, expected) in [
+        (KeyCode::Right, [(4, 0, 2), (4, 2, 4)]),
+        (KeyCode::Up, [(0, 2, 2), (4, 2, 4)]),
+        (KeyCode::Left, [(0, 0, 2), (0, 2, 4)]),
+        (KeyCode::Down, [(0, 0, 2), (4, 0, 4)]),
+    ] {
+        set_tiles(&mut app, &[(0, 0, 2), (4, 2, 4)]);
+        press(&mut app, key);
+        let after = tiles(&mut app);
+        assert!(expected.iter().all(|tile| after.contains(tile)), "{key:?}: {after:?}");
+    }
+
+    let checkerboard: Vec<(u8, u8, u32)> = (0..5).cartesian_product(0..3)
+        .map(|(x, y)| (x, y, if (x + y) % 2 == 0 { 2 } else { 4 }))
+        .collect();
+    set_tiles(&mut app, &checkerboard);
+    assert_eq!(app.world.resource::<NextState<RunState>>().0, Some(RunState::GameOver));
+}
+
+#[test]
+fn shaped_boards_skip_their_dead_cells() {
+    let mut app = game_app(97, GameMode::Endless, |settings| settings.shape = Some("plus".to_string()));
+
+    let board = app.world.query::<&Board>().single(&app.world);
+    assert_eq!((board.width, board.height), (6, 6));
+    let dead = board.dead.clone();
+    assert_eq!(app.world.query::<&TilePlaceholder>().iter(&app.world).count(), 20);
+
+    // the arms of the plus stop tiles where the board ends
+    set_tiles(&mut app, &[(3, 0, 2), (5, 2, 4)]);
+    press(&mut app, KeyCode::Left);
+    let after = tiles(&mut app);
+    assert!(after.contains(&(2, 0, 2)) && after.contains(&(0, 2, 4)), "{after:?}");
+
+    for _ in 0..20 {
+        press(&mut app, KeyCode::Right);
+        press(&mut app, KeyCode::Left);
+    }
+    assert!(tiles(&mut app).iter().all(|(x, y, _)| !dead.contains(&Position { x: *x, y: *y })));
+}
+
+#[test]
+fn threes_on_a_shape_ends_with_a_cut_off_cell_still_empty() {
+    let mut app = headless_app(97);
+    app.update();
+    // the top left cell has dead cells on both sides that could lead a card into it
+    app.world.resource_mut::<Shapes>().0.push(BoardShape {
+        name: "island".to_string(),
+        rows: Drawing([". x . .", "x x . .", ". . . .", ". . . ."].map(String::from).to_vec()),
+    });
+    {
+        let mut settings = app.world.resource_mut::<Settings>();
+        settings.shape = Some("island".to_string());
+        settings.variant = Variant::Threes;
+    }
+    app.world.resource_mut::<NextState<RunState>>().set(RunState::Playing);
+    app.update();
+
+    let dead = app.world.query::<&Board>().single(&app.world).dead.clone();
+    let cards: Vec<(u8, u8, u32)> = (0..4).cartesian_product(0..4)
+        .filter(|(x, y)| !dead.contains(&Position { x: *x, y: *y }) && (*x, *y) != (0, 3))
+        .map(|(x, y)| (x, y, if (x + y) % 2 == 0 { 3 } else { 6 }))
+        .collect();
+    set_tiles(&mut app, &cards);
+    assert_eq!(app.world.resource::<NextState<RunState>>().0, Some(RunState::GameOver));
+}
+
+#[test]
+fn puzzles_start_from_their_drawing_and_record_the_best() {
+    let mut app = game_app(3, GameMode::Puzzle(0), |_| {});
+    assert_eq!(tiles(&mut app), vec![(0, 0, 2), (0, 2, 4), (3, 0, 2)]);
+
+    press(&mut app, KeyCode::Left);
+    assert_eq!(tiles(&mut app), vec![(0, 0, 4), (0, 2, 4)], "no spawns left in this one");
+    press(&mut app, KeyCode::Down);
+    app.update();
+
+    assert_eq!(run_state(&app), RunState::Solved);
+    assert_eq!(app.world.resource::<PuzzleRecords>().best_moves["First merge"], 2);
+    assert!(!app.world.resource::<Game>().in_progress);
+}
+
+#[test]
+fn running_out_of_moves_loses_the_puzzle() {
+    let mut app = game_app(3, GameMode::Puzzle(0), |_| {});
+    press(&mut app, KeyCode::Up);
+    press(&mut app, KeyCode::Right);
+    app.update();
+
+    assert_eq!(run_state(&app), RunState::GameOver);
+    assert_eq!(app.world.resource::<Statistics>().games_played, 0, "puzzles stay out of the statistics");
+    assert!(app.world.resource::<PuzzleRecords>().best_moves.is_empty());
+}
+
+#[test]
+fn puzzle_spawns_come_in_order() {
+    let mut app = game_app(3, GameMode::Puzzle(2), |_| {});
+    let board = app.world.query::<&Board>().single(&app.world);
+    assert_eq!((board.width, board.height, board.dead.len()), (4, 4, 4));
+
+    press(&mut app, KeyCode::Left);
+    // the 2 2 on top merge, the first spawn goes to the first free cell from the top left
+    let after = tiles(&mut app);
+    assert!(after.contains(&(1, 3, 4)) && after.contains(&(2, 3, 2)), "{after:?}");
+    assert_eq!(progress(&mut app).spawn_queue, Some(vec![2, 4]));
+
+    // undo puts the spawn back in the queue
+    press(&mut app, KeyCode::U);
+    assert_eq!(progress(&mut app).spawn_queue, Some(vec![2, 2, 4]));
+}
+
+#[test]
+fn daily_tiles_depend_only_on_the_day() {
+    let mut first = game_app(1, GameMode::Daily(20_744), |settings| settings.board_width = 6);
+    let mut second = game_app(2, GameMode::Daily(20_744), |settings| settings.board_width = 6);
+    let board = first.world.query::<&Board>().single(&first.world);
+    assert_eq!((board.width, board.height), (DAILY_SIZE, DAILY_SIZE), "the settings don't apply");
+
+    for key in [KeyCode::Left, KeyCode::Up, KeyCode::Right, KeyCode::Down, KeyCode::Left] {
+        press(&mut first, key);
+        press(&mut second, key);
+    }
+    assert_eq!(tiles(&mut first), tiles(&mut second));
+
+    let mut other_day = game_app(1, GameMode::Daily(20_745), |settings| settings.board_width = 6);
+    for key in [KeyCode::Left, KeyCode::Up, KeyCode::Right, KeyCode::Down, KeyCode::Left] {
+        press(&mut other_day, key);
+    }
+    assert_ne!(tiles(&mut first), tiles(&mut other_day));
+}
+
+#[test]
+fn games_after_the_daily_deal_from_the_own_rng_again() {
+    let mut app = game_app(5, GameMode::Daily(20_744), |settings| settings.board_width = 6);
+    press(&mut app, KeyCode::Left);
+    app.world.resource_mut::<Game>().mode = GameMode::Endless;
+    app.world.resource_mut::<Game>().in_progress = false;
+    app.world.resource_mut::<NextState<RunState>>().set(RunState::MainMenu);
+    app.update();
+    app.world.resource_mut::<NextState<RunState>>().set(RunState::Playing);
+    app.update();
+
+    // the same as an endless game from the same seed with no daily before it
+    let mut fresh = game_app(5, GameMode::Endless, |settings| settings.board_width = 6);
+    assert_eq!(tiles(&mut app), tiles(&mut fresh));
+}
+
+#[test]
+fn daily_has_one_try_without_undo() {
+    let mut app = game_app(5, GameMode::Daily(20_744), |_| {});
+    assert_eq!(app.world.resource::<DailyRecords>().get(20_744), Some(&DailyResult::default()), "taken on the first tiles");
+
+    set_tiles(&mut app, &[(0, 0, 2), (3, 0, 2)]);
+    press(&mut app, KeyCode::Left);
+    press(&mut app, KeyCode::U);
+    assert!(tiles(&mut app).contains(&(0, 0, 4)), "undo does nothing on the daily");
+
+    let checkerboard: Vec<(u8, u8, u32)> = (0..4).cartesian_product(0..4)
+        .map(|(x, y)| (x, y, if (x + y) % 2 == 0 { 2 } else { 128 }))
+        .collect();
+    set_tiles(&mut app, &checkerboard);
+    app.update();
+
+    assert_eq!(run_state(&app), RunState::GameOver);
+    let result = app.world.resource::<DailyRecords>().get(20_744).cloned().unwrap();
+    assert!(result.finished);
+    assert_eq!((result.score, result.best_tile, result.moves), (4, 128, 1));
+    assert_eq!(result.rows[0], vec![128, 2, 128, 2]);
+}
+
+#[test]
+fn time_attack_ends_when_the_clock_runs_out() {
+    let mut app = game_app(53, GameMode::TimeAttack, |_| {});
+    app.world.resource_mut::<Game>().elapsed = Duration::from_secs(179);
+    app.update();
+    assert_eq!(run_state(&app), RunState::Playing);
+    assert!(app.world.resource::<Game>().time_left().is_some_and(|left| left <= Duration::from_secs(1)));
+
+    progress_mut(&mut app).score = 36;
+    app.world.resource_mut::<Game>().elapsed = Duration::from_secs(180);
+    app.update();
+    app.update();
+    assert_eq!(run_state(&app), RunState::GameOver);
+    let pending = app.world.resource::<PendingEntry>().0.clone().expect("first game always qualifies");
+    assert_eq!(pending.variant, "classic timed");
+}
+
+#[test]
+fn move_limit_ends_on_the_last_move() {
+    let mut app = game_app(59, GameMode::MoveLimit, |_| {});
+    progress_mut(&mut app).moves = 98;
+    set_tiles(&mut app, &[(0, 0, 2), (3, 0, 2)]);
+    press(&mut app, KeyCode::Left);
+    let left = progress(&mut app);
+    assert_eq!(app.world.resource::<Game>().moves_left(&left), Some(1));
+    assert_eq!(run_state(&app), RunState::Playing);
+
+    press(&mut app, KeyCode::Right);
+    app.update();
+    assert_eq!(run_state(&app), RunState::GameOver);
+    assert_eq!(app.world.resource::<Statistics>().total_moves, 100);
+}
+
+fn race_boards(app: &mut App) -> (Entity, Entity) {
+    let mut boards: Vec<(Controls, Entity)> = app.world.query::<(&Controls, Entity)>()
+        .iter(&app.world)
+        .map(|(controls, entity)| (*controls, entity))
+        .collect();
+    boards.sort();
+    assert_eq!(boards.iter().map(|(controls, _)| *controls).collect::<Vec<_>>(), vec![Controls::Wasd, Controls::Arrows]);
+    (boards[0].1, boards[1].1)
+}
+
+fn board_tiles(app: &mut App, board: Entity) -> Vec<(u8, u8, u32)> {
+    app.world.query::<(&Position, &Points, &Parent)>()
+        .iter(&app.world)
+        .filter(|(_, _, parent)| parent.get() == board)
+        .map(|(pos, points, _)| (pos.x, pos.y, points.value))
+        .sorted()
+        .collect()
+}
+
+#[test]
+fn race_boards_move_with_their_own_keys() {
+    let mut app = game_app(61, GameMode::Race, |_| {});
+    let (one, two) = race_boards(&mut app);
+    assert_eq!(board_tiles(&mut app, one).len(), 2);
+    assert_eq!(board_tiles(&mut app, two).len(), 2);
+
+    set_board_tiles(&mut app, one, &[(0, 0, 2), (3, 0, 2)]);
+    set_board_tiles(&mut app, two, &[(0, 0, 4), (3, 0, 4)]);
+    press(&mut app, KeyCode::A);
+    assert!(board_tiles(&mut app, one).contains(&(0, 0, 4)));
+    assert!(board_tiles(&mut app, two).contains(&(3, 0, 4)), "the arrows board stays put");
+
+    press(&mut app, KeyCode::Right);
+    assert!(board_tiles(&mut app, two).contains(&(3, 0, 8)));
+    let scores: Vec<u32> = [one, two].iter().map(|board| app.world.get::<Progress>(*board).unwrap().score).collect();
+    assert_eq!(scores, vec![4, 8]);
+
+    press(&mut app, KeyCode::U);
+    assert!(board_tiles(&mut app, two).contains(&(3, 0, 8)), "no undo in a race");
+}
+
+#[test]
+fn first_to_the_target_wins_the_race() {
+    let mut app = game_app(67, GameMode::Race, |_| {});
+    let (one, two) = race_boards(&mut app);
+    set_board_tiles(&mut app, two, &[(0, 0, 256), (0, 1, 256)]);
+    press(&mut app, KeyCode::Down);
+    app.update();
+
+    assert_eq!(run_state(&app), RunState::GameOver);
+    assert!(app.world.get::<Progress>(two).unwrap().won);
+    assert!(!app.world.get::<Progress>(one).unwrap().won);
+    assert_eq!(app.world.resource::<Statistics>().games_played, 0, "a race isn't anyone's statistics");
+    assert!(app.world.resource::<PendingEntry>().0.is_none());
+}
+
+#[test]
+fn race_goes_on_while_one_board_can_move() {
+    let mut app = game_app(71, GameMode::Race, |_| {});
+    let (one, two) = race_boards(&mut app);
+    let checkerboard: Vec<(u8, u8, u32)> = (0..4).cartesian_product(0..4)
+        .map(|(x, y)| (x, y, if (x + y) % 2 == 0 { 2 } else { 64 }))
+        .collect();
+    set_board_tiles(&mut app, one, &checkerboard);
+    app.update();
+    assert_eq!(run_state(&app), RunState::Playing);
+
+    set_board_tiles(&mut app, two, &checkerboard);
+    app.update();
+    assert_eq!(run_state(&app), RunState::GameOver);
+}
+
+fn compare_boards(app: &mut App) -> Vec<(Variant, Entity)> {
+    app.world.query::<(&BoardIndex, &Rules, Entity)>()
+        .iter(&app.world)
+        .sorted_by_key(|(index, _, _)| **index)
+        .map(|(_, rules, entity)| (rules.variant, entity))
+        .collect()
+}
+
+#[test]
+fn compare_plays_every_variant_with_the_same_keys() {
+    let mut app = game_app(73, GameMode::Compare, |_| {});
+    let boards = compare_boards(&mut app);
+    assert_eq!(boards.iter().map(|(variant, _)| *variant).collect::<Vec<_>>(), Variant::ALL.to_vec());
+    let labels: Vec<String> = app.world.query_filtered::<&Text, With<BoardLabel>>()
+        .iter(&app.world)
+        .map(|text| text.sections[0].value.clone())
+        .sorted()
+        .collect();
+    assert_eq!(labels, vec!["classic", "fibonacci", "threes", "walls"]);
+
+    for (_, board) in &boards {
+        set_board_tiles(&mut app, *board, &[(0, 0, 2), (3, 0, 2)]);
+    }
+    press(&mut app, KeyCode::Left);
+    let (classic, threes) = (boards[0].1, boards[3].1);
+    assert!(board_tiles(&mut app, classic).contains(&(0, 0, 4)));
+    assert!(board_tiles(&mut app, threes).contains(&(2, 0, 2)), "threes tiles step a single cell");
+    assert_eq!(app.world.get::<Progress>(classic).unwrap().score, 4);
+    assert_eq!(app.world.get::<Progress>(threes).unwrap().score, 0);
+}
+
+#[test]
+fn undo_takes_back_each_board_on_its_own() {
+    let mut app = game_app(79, GameMode::Compare, |_| {});
+    let boards = compare_boards(&mut app);
+    let (classic, fibonacci) = (boards[0].1, boards[2].1);
+    set_board_tiles(&mut app, classic, &[(0, 0, 2), (3, 0, 2)]);
+    set_board_tiles(&mut app, fibonacci, &[(0, 0, 2), (1, 0, 5)]);
+
+    // nothing slides on the fibonacci board, the move still counts there and brings a tile
+    press(&mut app, KeyCode::Left);
+    assert_eq!(board_tiles(&mut app, classic).len(), 2);
+    assert_eq!(board_tiles(&mut app, fibonacci).len(), 3);
+    press(&mut app, KeyCode::U);
+    assert_eq!(board_tiles(&mut app, classic), vec![(0, 0, 2), (3, 0, 2)]);
+    assert_eq!(board_tiles(&mut app, fibonacci), vec![(0, 0, 2), (1, 0, 5)]);
+    assert_eq!(app.world.get::<Progress>(classic).unwrap().score, 0);
+}
+
+// a versus against the two ends of a local connection, the test plays the server and the opponent
+fn versus_app(seed: u64, garbage: bool) -> (App, std::sync::mpsc::Sender<Message>, std::sync::mpsc::Receiver<Message>) {
+    let mut app = headless_app(0);
+    app.insert_resource(ServerAddress("nowhere".to_string()));
+    app.update();
+    app.world.resource_mut::<NextState<RunState>>().set(RunState::Lobby);
+    app.update();
+
+    let (connection, to_game, from_game) = Connection::local();
+    app.world.resource_mut::<Online>().connection = Some(connection);
+    to_game.send(Message::Start { seed, garbage }).unwrap();
+    app.update();  // the start comes in
+    app.update();  // and the game with it
+    assert_eq!(run_state(&app), RunState::Playing);
+    (app, to_game, from_game)
+}
+
+fn own_and_remote(app: &mut App) -> (Entity, Entity) {
+    let boards: Vec<(Controls, Entity)> = app.world.query::<(&Controls, Entity)>()
+        .iter(&app.world)
+        .map(|(controls, entity)| (*controls, entity))
+        .sorted()
+        .collect();
+    assert_eq!(boards.iter().map(|(controls, _)| *controls).collect::<Vec<_>>(), vec![Controls::Bindings, Controls::Remote]);
+    (boards[0].1, boards[1].1)
+}
+
+fn last_board(from_game: &std::sync::mpsc::Receiver<Message>) -> Option<Message> {
+    from_game.try_iter().filter(|message| matches!(message, Message::Board { .. })).last()
+}
+
+#[test]
+fn versus_deals_from_the_servers_seed_and_sends_every_move() {
+    let (mut app, _to_game, from_game) = versus_app(83, false);
+    let (own, remote) = own_and_remote(&mut app);
+    assert!(board_tiles(&mut app, remote).is_empty(), "the opponent's tiles only come over the network");
+
+    let mut same_seed = game_app(83, GameMode::Versus, |_| {});
+    let (other_own, _) = own_and_remote(&mut same_seed);
+    assert_eq!(board_tiles(&mut app, own), board_tiles(&mut same_seed, other_own));
+
+    set_board_tiles(&mut app, own, &[(0, 0, 2), (3, 0, 2)]);
+    press(&mut app, KeyCode::Left);
+    let Some(Message::Board { tiles, score, moves, .. }) = last_board(&from_game) else { panic!("a move sends the board") };
+    assert_eq!(tiles.len(), 2, "the merged tile and the new one: {tiles:?}");
+    assert!(tiles.contains(&(0, 0, 4)));
+    assert_eq!((score, moves), (4, 1));
+}
+
+#[test]
+fn versus_mirrors_the_opponent_and_takes_their_garbage() {
+    let (mut app, to_game, from_game) = versus_app(89, true);
+    let (own, remote) = own_and_remote(&mut app);
+
+    to_game.send(Message::Board { tiles: vec![(0, 0, 8), (1, 0, 2)], walls: vec![(3, 3)], score: 12, moves: 5 }).unwrap();
+    app.update();
+    app.update();
+    assert_eq!(board_tiles(&mut app, remote), vec![(0, 0, 8), (1, 0, 2)]);
+    assert_eq!(app.world.get::<Progress>(remote).unwrap().score, 12);
+
+    let walls_on = |app: &mut App, board: Entity| app.world.query_filtered::<&Parent, With<Wall>>()
+        .iter(&app.world)
+        .filter(|parent| parent.get() == board)
+        .count();
+    assert_eq!(walls_on(&mut app, remote), 1);
+    to_game.send(Message::Garbage { count: 2 }).unwrap();
+    app.update();
+    app.update();
+    assert_eq!(walls_on(&mut app, own), 2);
+
+    // a big merge sends some back
+    from_game.try_iter().count();
+    set_board_tiles(&mut app, own, &[(0, 0, 32), (3, 0, 32)]);
+    press(&mut app, KeyCode::Left);
+    assert!(from_game.try_iter().any(|message| message == Message::Garbage { count: 1 }));
+
+    to_game.send(Message::Left).unwrap();
+    app.update();
+    app.update();
+    assert_eq!(run_state(&app), RunState::GameOver);
+    assert!(app.world.resource::<Online>().connection.is_none());
+}
+
+#[test]
+fn spectators_see_the_state_the_boards_and_the_moves() {
+    let spectators = Spectators::listen("127.0.0.1:0").unwrap();
+    let stream = std::net::TcpStream::connect(spectators.address).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let (mut socket, _) = tungstenite::client(format!("ws://{}", spectators.address), stream).unwrap();
+
+    let mut app = headless_app(97);
+    app.insert_resource(spectators);
+    app.world.resource_mut::<NextState<RunState>>().set(RunState::Playing);
+    app.update();
+    set_tiles(&mut app, &[(2, 0, 2), (3, 0, 2)]);
+    press(&mut app, KeyCode::Left);
+
+    let mut received: Vec<serde_json::Value> = Vec::new();
+    let done = |received: &Vec<serde_json::Value>| received.iter().any(|message| message["type"] == "move")
+        && received.iter().any(|message| message["type"] == "board" && message["moves"] == 1);
+    while !done(&received) {
+        let text = socket.read().expect("everything arrives well within the timeout").into_text().unwrap();
+        received.push(serde_json::from_str(&text).unwrap());
+    }
+
+    assert!(received.contains(&serde_json::json!({"type": "state", "state": "playing", "mode": "endless"})));
+    assert!(received.contains(&serde_json::json!({
+        "type": "move", "board": 0, "direction": "left", "gain": 4, "merges": [[0, 0, 4]],
+    })));
+    let last_board = received.iter().rev().find(|message| message["type"] == "board").unwrap();
+    assert_eq!(last_board["name"], "You");
+    assert_eq!(last_board["score"], 4);
+    assert_eq!(last_board["tiles"].as_array().unwrap().len(), 2, "the merged tile and the new one");
+    assert!(last_board["tiles"].as_array().unwrap().contains(&serde_json::json!([0, 0, 4])));
+}
+
+#[test]
+fn a_spectator_without_a_handshake_holds_up_nobody() {
+    let spectators = Spectators::listen("127.0.0.1:0").unwrap();
+    let _silent = std::net::TcpStream::connect(spectators.address).unwrap();
+    spectators.publish(Broadcast::State { state: RunState::Playing, mode: GameMode::Endless });
+
+    let stream = std::net::TcpStream::connect(spectators.address).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(1))).unwrap();  // shorter than the silent one gets
+    let (mut socket, _) = tungstenite::client(format!("ws://{}", spectators.address), stream).unwrap();
+    let text = socket.read().expect("the latest state, right after the handshake").into_text().unwrap();
+    assert_eq!(serde_json::from_str::<serde_json::Value>(&text).unwrap()["state"], "playing");
+}
+
+#[test]
+fn gym_environment_plays_like_the_game() {
+    for variant in Variant::ALL {
+        let mut app = game_app(101, GameMode::Endless, |settings| settings.variant = variant);
+
+        let mut env = gym::Env::new(&gym::EnvConfig::from_settings(app.world.resource::<Settings>()));
+        env.reset(101);
+        let board = board_entity(&mut app);
+        for action in [BoardShift::Left, BoardShift::Up, BoardShift::Right, BoardShift::Down].repeat(6) {
+            let walls: Vec<(u8, u8)> = app.world.query_filtered::<&Position, With<Wall>>()
+                .iter(&app.world)
+                .map(|pos| (pos.x, pos.y))
+                .sorted()
+                .collect();
+            let observation = env.observation();
+            let cells: Vec<(u8, u8, u32)> = (0..observation.height).rev()
+                .flat_map(|y| (0..observation.width).map(move |x| (x, y)))
+                .zip(&observation.cells)
+                .filter(|(_, value)| **value > 0)
+                .map(|((x, y), value)| (x, y, *value))
+                .sorted()
+                .collect();
+            let blocked: Vec<(u8, u8)> = (0..observation.height).rev()
+                .flat_map(|y| (0..observation.width).map(move |x| (x, y)))
+                .zip(&observation.blocked)
+                .filter(|(_, blocked)| **blocked)
+                .map(|(cell, _)| cell)
+                .sorted()
+                .collect();
+            assert_eq!(tiles(&mut app), cells, "{variant:?}");
+            assert_eq!(walls, blocked, "{variant:?}");
+            assert_eq!(progress(&mut app).score, observation.score);
+
+            app.world.send_event(ShiftEvent { board, shift: action });
+            app.update();
+            env.step(action);
+        }
+    }
+}
+
+#[test]
+fn bot_plays_through_the_normal_moves_until_it_is_stuck() {
+    let script = ScriptBot::new("lefty", "while read board; do echo L; done\n");
+    let mut app = headless_app(7);
+    app.insert_resource(BotPlayer::new(Bot::spawn(&script.command).unwrap()));
+    app.world.resource_mut::<NextState<RunState>>().set(RunState::Playing);
+    app.update();
+    assert_eq!(app.world.resource::<BotPlayer>().label(), "Bot: lefty");
+
+    // left until nothing slides any more, the answers come in on frames of their own
+    for _ in 0..500 {
+        if app.world.resource::<BotPlayer>().stopped.is_some() { break; }
+        app.update();
+        std::thread::sleep(std::time::Duration::from_millis(2));
+    }
+    assert_eq!(app.world.resource::<BotPlayer>().stopped, Some(BotError::Stuck));
+    assert_eq!(app.world.resource::<BotPlayer>().label(), "Bot: lefty (stuck)");
+    assert!(progress(&mut app).moves >= 1);
+
+    // a new game gives it another go
+    app.world.resource_mut::<Game>().in_progress = false;
+    app.world.resource_mut::<NextState<RunState>>().set(RunState::MainMenu);
+    app.update();
+    app.world.resource_mut::<NextState<RunState>>().set(RunState::Playing);
+    app.update();
+    assert_eq!(app.world.resource::<BotPlayer>().stopped, None);
+    assert_eq!(progress(&mut app).moves, 0);
+}
+
+#[test]
+fn bot_still_thinking_when_the_game_ends_starts_over() {
+    // the first run takes its time over a bad answer, the runs after it answer left straight away
+    let script = ScriptBot::new("slow", "started=\"$(dirname \"$0\")/started\"\n\
+        if [ ! -e \"$started\" ]; then touch \"$started\"; read board; sleep 0.5; echo X; fi\n\
+        while read board; do echo L; done\n");
+    let mut app = headless_app(7);
+    app.insert_resource(BotPlayer::new(Bot::spawn(&script.command).unwrap()));
+    app.world.resource_mut::<NextState<RunState>>().set(RunState::Playing);
+    app.update();
+
+    // ended before the answer is in, like on a time-out
+    app.world.resource_mut::<Game>().in_progress = false;
+    app.world.resource_mut::<NextState<RunState>>().set(RunState::MainMenu);
+    app.update();
+    app.world.resource_mut::<NextState<RunState>>().set(RunState::Playing);
+    app.update();
+    for _ in 0..500 {
+        if progress(&mut app).moves > 0 || app.world.resource::<BotPlayer>().stopped.is_some() { break; }
+        app.update();
+        std::thread::sleep(std::time::Duration::from_millis(2));
+    }
+    assert!(!matches!(app.world.resource::<BotPlayer>().stopped, Some(BotError::BadAnswer(_))), "the old answer never arrives");
+    assert!(progress(&mut app).moves > 0);
+}
+
+#[test]
+fn bot_only_gets_stuck_on_the_board_it_was_shown() {
+    // one left, late enough for the boards to be set up, and then nothing
+    let script = ScriptBot::new("once", "read board; sleep 0.5; echo L; while read board; do :; done\n");
+    let mut app = headless_app(7);
+    app.insert_resource(BotPlayer::new(Bot::spawn(&script.command).unwrap()));
+    app.update();
+    app.world.resource_mut::<Game>().mode = GameMode::Compare;
+    app.world.resource_mut::<NextState<RunState>>().set(RunState::Playing);
+    app.update();
+    let boards = compare_boards(&mut app);
+    let (classic, fibonacci) = (boards[0].1, boards[2].1);
+    for (_, board) in &boards {
+        set_board_tiles(&mut app, *board, &[(0, 0, 2), (3, 0, 2)]);
+    }
+    // nothing slides left on the fibonacci board, the bot looks at the classic one
+    set_board_tiles(&mut app, fibonacci, &[(0, 0, 2), (1, 0, 5)]);
+
+    for _ in 0..500 {
+        if app.world.get::<Progress>(classic).unwrap().moves > 0 { break; }
+        app.update();
+        std::thread::sleep(std::time::Duration::from_millis(2));
+    }
+    assert_eq!(app.world.get::<Progress>(fibonacci).unwrap().moves, 1);
+    app.update();
+    app.update();
+    assert_eq!(app.world.resource::<BotPlayer>().stopped, None);
+}
+
+#[test]
+fn lobby_follows_the_connection() {
+    let mut app = headless_app(0);
+    app.add_event::<bevy::window::ReceivedCharacter>()
+        .init_resource::<UiScale>()
+        .add_plugin(GameUiPlugin)
+        .insert_resource(ServerAddress("nowhere".to_string()));
+    app.update();
+    app.world.resource_mut::<NextState<RunState>>().set(RunState::Lobby);
+    app.update();
+    let lobby_line = |app: &mut App| app.world.query_filtered::<&Text, With<LobbyText>>().single(&app.world).sections[0].value.clone();
+    // connecting happens off the frame, failing to shows up on a later one
+    for _ in 0..200 {
+        if app.world.resource::<Online>().connection.is_none() { break; }
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        app.update();
+    }
+    assert!(lobby_line(&mut app).starts_with("No connection"), "there is no server at nowhere");
+
+    let (connection, to_game, _from_game) = Connection::local();
+    app.world.resource_mut::<Online>().connection = Some(connection);
+    app.update();
+    assert!(lobby_line(&mut app).starts_with("Waiting for an opponent"));
+
+    // the server going away while waiting
+    to_game.send(Message::Left).unwrap();
+    app.update();
+    assert_eq!(run_state(&app), RunState::Lobby);
+    assert!(lobby_line(&mut app).starts_with("No connection"));
+}
+
+#[test]
+fn hanging_up_sends_what_is_still_queued() {
+    use std::io::BufRead;
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let connection = Connection::connect(&listener.local_addr().unwrap().to_string());
+    let (server, _) = listener.accept().unwrap();
+    for moves in 0..500 {
+        connection.send(Message::Board { tiles: vec![(0, 0, 2)], walls: vec![], score: 0, moves });
+    }
+    drop(connection);
+
+    let received: Vec<Message> = std::io::BufReader::new(server).lines()
+        .map_while(Result::ok)
+        .filter_map(|line| Message::from_line(&line))
+        .collect();
+    assert_eq!(received.len(), 500);
+    assert_eq!(received.last(), Some(&Message::Board { tiles: vec![(0, 0, 2)], walls: vec![], score: 0, moves: 499 }));
+}
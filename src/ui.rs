@@ -1,6 +1,32 @@
+use std::time::Duration;
 use bevy::prelude::*;
-use crate::{FontSpec, Game, RunState};
+use bevy::window::PrimaryWindow;
+use itertools::Itertools;
+use bevy_easings::*;
+use crate::{Board, BoardIndex, Controls, FontSpec, Game, GameMode, Progress, Rules, RunState, ScoreGainEvent, TILE_SIZE};
+use crate::calendar::{format_day, today};
+use crate::daily::{export_daily, DailyRecords};
+use crate::leaderboard::{Leaderboard, PendingEntry, submit_high_score};
+use crate::levels::Shapes;
+use crate::bot::BotPlayer;
+use crate::net::{connect_to_server, Online, ServerAddress};
+use crate::puzzles::{PuzzleRecords, Puzzles};
+use crate::settings::{KeyAction, SettingField, Settings};
+use crate::stats::Statistics;
+use crate::storage::{Profile, Storage};
 
+const MAX_NAME_LENGTH: usize = 12;
+const POPUP_DURATION: Duration = Duration::from_millis(800);
+const POPUP_RISE: f32 = 40.0;
+// the ui was laid out for bevy's default 1280x720 window
+const REFERENCE_HEIGHT: f32 = 720.0;
+// share of the free space below the hud the board takes up
+const BOARD_MARGIN: f32 = 0.9;
+
+
+// the title and score panels along the top of the window
+#[derive(Component)]
+pub struct Hud;
 
 #[derive(Component)]
 pub struct ScoreDisplay;
@@ -8,14 +34,115 @@ pub struct ScoreDisplay;
 #[derive(Component)]
 pub struct BestScoreDisplay;
 
+#[derive(Component)]
+pub struct MovesDisplay;
+
+#[derive(Component)]
+pub struct TimeDisplay;
+
+#[derive(Component)]
+pub struct BotDisplay;
+
+#[derive(Component, Clone, Copy, PartialEq, Eq)]
+pub enum ButtonAction {
+    Play,
+    Start(GameMode),  // a new game, even with one in progress
+    Puzzles,
+    Daily,
+    ExportDaily(i64),
+    Online,
+    EndGame,
+    MainMenu,
+    Statistics,
+    Leaderboard,
+    Settings,
+    Quit,
+    CycleSizeFilter,
+    CycleVariantFilter,
+    CycleShape,
+    CycleProfile,
+    Adjust(SettingField, i8),
+    Rebind(KeyAction),
+}
+
+// the menu of one RunState, despawned as soon as the state is left
+#[derive(Component)]
+pub struct StateScreen;
+
+// above whatever screen is shown, as long as a high score waits for a name
+#[derive(Component)]
+pub struct NameEntryScreen;
+
+#[derive(Component)]
+pub struct LeaderboardText;
+
+#[derive(Component)]
+pub struct LobbyText;
+
+#[derive(Component)]
+pub struct NameEntryText;
+
+#[derive(Component)]
+pub struct SettingValue(SettingField);
+
+// the key binding waiting for the next key press
+#[derive(Resource, Default)]
+pub struct Rebinding(Option<KeyAction>);
+
+// a "+N" that rises and fades away, then despawns itself
+#[derive(Component)]
+pub struct Popup {
+    timer: Timer,
+}
+
+impl Default for Popup {
+    fn default() -> Self {
+        Popup { timer: Timer::new(POPUP_DURATION, TimerMode::Once) }
+    }
+}
+
+// what the leaderboard screen shows, None is everything
+#[derive(Resource, Default)]
+pub struct LeaderboardFilter {
+    board: Option<(u8, u8)>,
+    variant: Option<String>,
+}
+
 pub struct GameUiPlugin;
 
 impl Plugin for GameUiPlugin {
     fn build(&self, app: &mut App) {
-        app.add_startup_system(setup_ui)
+        app.init_resource::<LeaderboardFilter>()
+            .init_resource::<Rebinding>()
+            .add_startup_system(setup_ui)
+            .add_system(spawn_main_menu.in_schedule(OnEnter(RunState::MainMenu)))
+            .add_system(spawn_pause_screen.in_schedule(OnEnter(RunState::Paused)))
+            .add_system(spawn_won_screen.in_schedule(OnEnter(RunState::Won)))
+            .add_system(spawn_game_over_screen.in_schedule(OnEnter(RunState::GameOver)))
+            .add_system(spawn_statistics_screen.in_schedule(OnEnter(RunState::Statistics)))
+            .add_system(spawn_leaderboard_screen.in_schedule(OnEnter(RunState::Leaderboard)))
+            .add_system(spawn_settings_screen.in_schedule(OnEnter(RunState::Settings)))
+            .add_system(spawn_puzzles_screen.in_schedule(OnEnter(RunState::Puzzles)))
+            .add_system(spawn_solved_screen.in_schedule(OnEnter(RunState::Solved)))
+            .add_system(spawn_daily_screen.in_schedule(OnEnter(RunState::Daily)))
+            .add_system(spawn_lobby_screen.after(connect_to_server).in_schedule(OnEnter(RunState::Lobby)))
             .add_system(scoreboard)
+            .add_system(bot_display)
             .add_system(button_interaction_system)
-            .add_system(button_text_system);
+            .add_system(button_text_system)
+            .add_system(leaderboard_text.in_set(OnUpdate(RunState::Leaderboard)))
+            .add_system(lobby_text.in_set(OnUpdate(RunState::Lobby)))
+            .add_systems((settings_text, rebind_key).in_set(OnUpdate(RunState::Settings)))
+            .add_system(stop_rebinding.in_schedule(OnExit(RunState::Settings)))
+            .add_system(name_entry)
+            .add_system(spawn_score_popups)
+            .add_system(fade_popups)
+            .add_system(scale_ui)
+            .add_system(fit_board_to_window);
+
+        for state in RunState::variants() {
+            app.add_system(despawn_state_screens.in_schedule(OnExit(state)));
+        }
     }
 }
 
@@ -24,25 +151,38 @@ impl Plugin for GameUiPlugin {
 fn setup_ui (mut commands: Commands, font_spec: Res<FontSpec>) {
     commands.spawn(NodeBundle {
         style: Style {
-            size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+            size: Size::new(Val::Percent(100.0), Val::Auto),
             align_items: AlignItems::FlexStart,
             padding: UiRect::all(Val::Px(50.0)),
             ..default()
         },
         ..default()
     })
+        .insert(Hud)
         .with_children(|parent| {
+            // the title, and under it the bot playing when there is one
             parent.spawn(TextBundle{
-                text: Text::from_section(
-                    "2048",
-                    TextStyle {
-                        font: font_spec.family.clone(),
-                        font_size: 40.0,
-                        color: Color::WHITE,
-                    }
-                ).with_alignment(TextAlignment::Center),
+                text: Text::from_sections([
+                    TextSection::new(
+                        "2048",
+                        TextStyle {
+                            font: font_spec.family.clone(),
+                            font_size: 40.0,
+                            color: Color::WHITE,
+                        }
+                    ),
+                    TextSection::new(
+                        "",
+                        TextStyle {
+                            font: font_spec.family.clone(),
+                            font_size: 16.0,
+                            color: Color::WHITE,
+                        }
+                    ),
+                ]).with_alignment(TextAlignment::Center),
                 ..default()
-            });
+            })
+                .insert(BotDisplay);
             parent.spawn(NodeBundle {
                 style: Style {
                     justify_content: JustifyContent::Center,
@@ -51,133 +191,498 @@ fn setup_ui (mut commands: Commands, font_spec: Res<FontSpec>) {
                 },
                 ..default()
             }).with_children(|parent| {
-                parent.spawn(NodeBundle {
-                   style: Style {
-                       flex_direction: FlexDirection::Column,
-                       align_items: AlignItems::Center,
-                       margin: UiRect {
-                           left: Val::Px(20.0),
-                           right: Val::Px(20.0),
-                           top: Val::Px(0.0),
-                           bottom: Val::Px(0.0),
-                       },
-                       padding: UiRect::all(Val::Px(10.0)),
-                       ..default()
-                   },
-                   background_color: NORMAL_BUTTON.into(),
-                   ..default()
-                }).with_children(|parent| {
-                    parent.spawn(TextBundle {
-                        text: Text::from_section(
-                            "Score",
-                            TextStyle {
-                                font: font_spec.family.clone(),
-                                font_size: 15.0,
-                                color: Color::WHITE,
-                            }
-                        ).with_alignment(TextAlignment::Center),
-                        ..default()
+                spawn_panel(parent, &font_spec, "Score", ScoreDisplay);
+                spawn_panel(parent, &font_spec, "Best", BestScoreDisplay);
+                spawn_panel(parent, &font_spec, "Moves", MovesDisplay);
+                spawn_panel(parent, &font_spec, "Time", TimeDisplay);
+            });
+        });
+
+    let name_entry = spawn_overlay(&mut commands, |parent| {
+        parent.spawn(TextBundle {
+            text: Text::from_section(
+                "New high score! Type your name, Enter to save",
+                TextStyle {
+                    font: font_spec.family.clone(),
+                    font_size: 20.0,
+                    color: Color::WHITE,
+                }
+            ),
+            ..default()
+        });
+        parent.spawn(screen_text(&font_spec)).insert(NameEntryText);
+    });
+    commands.entity(name_entry)
+        .insert(NameEntryScreen)
+        .insert(Visibility::Hidden)
+        .insert(ZIndex::Global(1));
+}
+
+fn spawn_main_menu(mut commands: Commands, font_spec: Res<FontSpec>, game: Res<Game>) {
+    spawn_screen(&mut commands, &font_spec, "2048", |parent| {
+        if game.in_progress {
+            spawn_button(parent, &font_spec, "Continue", ButtonAction::Play);
+        } else {
+            spawn_button(parent, &font_spec, "New Game", ButtonAction::Start(GameMode::Endless));
+        }
+        spawn_button(parent, &font_spec, "Time Attack", ButtonAction::Start(GameMode::TimeAttack));
+        spawn_button(parent, &font_spec, "100 Moves", ButtonAction::Start(GameMode::MoveLimit));
+        spawn_button(parent, &font_spec, "Two Players", ButtonAction::Start(GameMode::Race));
+        spawn_button(parent, &font_spec, "Compare", ButtonAction::Start(GameMode::Compare));
+        spawn_button(parent, &font_spec, "Online", ButtonAction::Online);
+        spawn_button(parent, &font_spec, "Puzzles", ButtonAction::Puzzles);
+        spawn_button(parent, &font_spec, "Daily", ButtonAction::Daily);
+        spawn_button(parent, &font_spec, "Statistics", ButtonAction::Statistics);
+        spawn_button(parent, &font_spec, "Leaderboard", ButtonAction::Leaderboard);
+        spawn_button(parent, &font_spec, "Settings", ButtonAction::Settings);
+        spawn_button(parent, &font_spec, "Quit", ButtonAction::Quit);
+    });
+}
+
+fn spawn_pause_screen(mut commands: Commands, font_spec: Res<FontSpec>) {
+    spawn_screen(&mut commands, &font_spec, "Paused", |parent| {
+        spawn_button(parent, &font_spec, "Resume", ButtonAction::Play);
+        spawn_button(parent, &font_spec, "End Game", ButtonAction::EndGame);
+        spawn_button(parent, &font_spec, "Main Menu", ButtonAction::MainMenu);
+    });
+}
+
+fn spawn_won_screen(mut commands: Commands, font_spec: Res<FontSpec>) {
+    spawn_screen(&mut commands, &font_spec, "You made 2048!", |parent| {
+        spawn_button(parent, &font_spec, "Keep Going", ButtonAction::Play);
+        spawn_button(parent, &font_spec, "End Game", ButtonAction::EndGame);
+        spawn_button(parent, &font_spec, "Main Menu", ButtonAction::MainMenu);
+    });
+}
+
+fn spawn_game_over_screen(
+    mut commands: Commands,
+    font_spec: Res<FontSpec>,
+    game: Res<Game>,
+    boards: Query<(&BoardIndex, &Controls, &Rules, &Progress)>,
+) {
+    spawn_screen(&mut commands, &font_spec, "Game Over", |parent| {
+        let mut text = screen_text(&font_spec);
+        text.text.sections[0].value = result_line(boards.iter()
+            .sorted_by_key(|(index, _, _, _)| **index)
+            .map(|(_, controls, rules, progress)| (controls.board_name(rules, game.mode), controls.is_you(game.mode), progress))
+            .collect());
+        parent.spawn(text);
+        // the daily has no second try, a rematch online goes through the lobby again,
+        // playing on after a lost puzzle starts the same puzzle over
+        match game.mode {
+            GameMode::Daily(day) => {
+                spawn_button(parent, &font_spec, "Export", ButtonAction::ExportDaily(day));
+                spawn_button(parent, &font_spec, "Daily", ButtonAction::Daily);
+            }
+            GameMode::Versus => spawn_button(parent, &font_spec, "Rematch", ButtonAction::Online),
+            GameMode::Puzzle(_) => {
+                spawn_button(parent, &font_spec, "Retry", ButtonAction::Play);
+                spawn_button(parent, &font_spec, "Puzzles", ButtonAction::Puzzles);
+            }
+            _ => spawn_button(parent, &font_spec, "New Game", ButtonAction::Play),
+        }
+        spawn_button(parent, &font_spec, "Main Menu", ButtonAction::MainMenu);
+    });
+}
+
+fn spawn_puzzles_screen(mut commands: Commands, font_spec: Res<FontSpec>, puzzles: Res<Puzzles>, records: Res<PuzzleRecords>) {
+    spawn_screen(&mut commands, &font_spec, "Puzzles", |parent| {
+        for (index, puzzle) in puzzles.0.iter().enumerate() {
+            let best = match records.best_moves.get(&puzzle.name) {
+                Some(moves) => format!("best {moves}"),
+                None => "unsolved".to_string(),
+            };
+            let label = format!("{} - {} ({best})", puzzle.name, puzzle.goal.describe());
+            spawn_button(parent, &font_spec, &label, ButtonAction::Start(GameMode::Puzzle(index)));
+        }
+        spawn_button(parent, &font_spec, "Back", ButtonAction::MainMenu);
+    });
+}
+
+fn spawn_solved_screen(
+    mut commands: Commands,
+    font_spec: Res<FontSpec>,
+    game: Res<Game>,
+    boards: Query<&Progress>,
+    puzzles: Res<Puzzles>,
+    records: Res<PuzzleRecords>,
+) {
+    let GameMode::Puzzle(index) = game.mode else { return; };
+    let (Some(puzzle), Ok(progress)) = (puzzles.0.get(index), boards.get_single()) else { return; };
+    spawn_screen(&mut commands, &font_spec, "Solved!", |parent| {
+        let mut text = screen_text(&font_spec);
+        let best = records.best_moves.get(&puzzle.name).copied().unwrap_or(progress.moves);
+        text.text.sections[0].value = format!("{} in {} moves, best {best}", puzzle.name, progress.moves);
+        parent.spawn(text);
+        spawn_button(parent, &font_spec, "Retry", ButtonAction::Play);
+        if index + 1 < puzzles.0.len() {
+            spawn_button(parent, &font_spec, "Next Puzzle", ButtonAction::Start(GameMode::Puzzle(index + 1)));
+        }
+        spawn_button(parent, &font_spec, "Puzzles", ButtonAction::Puzzles);
+        spawn_button(parent, &font_spec, "Main Menu", ButtonAction::MainMenu);
+    });
+}
+
+fn spawn_daily_screen(mut commands: Commands, font_spec: Res<FontSpec>, records: Res<DailyRecords>) {
+    let day = today();
+    spawn_screen(&mut commands, &font_spec, &format!("Daily {}", format_day(day)), |parent| {
+        let mut text = screen_text(&font_spec);
+        text.text.sections[0].value = daily_lines(&records, day);
+        parent.spawn(text);
+        match records.get(day) {
+            None => spawn_button(parent, &font_spec, "Play", ButtonAction::Start(GameMode::Daily(day))),
+            Some(result) if result.finished => spawn_button(parent, &font_spec, "Export", ButtonAction::ExportDaily(day)),
+            Some(_) => {}
+        }
+        spawn_button(parent, &font_spec, "Back", ButtonAction::MainMenu);
+    });
+}
+
+fn spawn_lobby_screen(mut commands: Commands, font_spec: Res<FontSpec>, online: Res<Online>, address: Res<ServerAddress>) {
+    spawn_screen(&mut commands, &font_spec, "Online", |parent| {
+        let mut text = screen_text(&font_spec);
+        text.text.sections[0].value = lobby_line(&online, &address);
+        parent.spawn(text).insert(LobbyText);
+        spawn_button(parent, &font_spec, "Back", ButtonAction::MainMenu);
+    });
+}
+
+// the connection can go while waiting, the server going away or never answering
+fn lobby_text(online: Res<Online>, address: Res<ServerAddress>, mut text_query: Query<&mut Text, With<LobbyText>>) {
+    if !online.is_changed() { return; }
+    if let Ok(mut text) = text_query.get_single_mut() {
+        text.sections[0].value = lobby_line(&online, &address);
+    }
+}
+
+fn lobby_line(online: &Online, address: &ServerAddress) -> String {
+    match online.connection {
+        Some(_) => format!("Waiting for an opponent on {}", address.0),
+        None => format!("No connection to {}, start a server with cargo run --bin server", address.0),
+    }
+}
+
+fn spawn_statistics_screen(mut commands: Commands, font_spec: Res<FontSpec>, statistics: Res<Statistics>) {
+    spawn_screen(&mut commands, &font_spec, "Statistics", |parent| {
+        let mut text = screen_text(&font_spec);
+        text.text.sections[0].value = statistics_lines(&statistics);
+        parent.spawn(text);
+        spawn_button(parent, &font_spec, "Back", ButtonAction::MainMenu);
+    });
+}
+
+fn spawn_leaderboard_screen(
+    mut commands: Commands,
+    font_spec: Res<FontSpec>,
+    leaderboard: Res<Leaderboard>,
+    filter: Res<LeaderboardFilter>,
+) {
+    spawn_screen(&mut commands, &font_spec, "Leaderboard", |parent| {
+        parent.spawn(NodeBundle::default()).with_children(|parent| {
+            spawn_button(parent, &font_spec, "Size", ButtonAction::CycleSizeFilter);
+            spawn_button(parent, &font_spec, "Variant", ButtonAction::CycleVariantFilter);
+        });
+        let mut text = screen_text(&font_spec);
+        text.text.sections[0].value = leaderboard_lines(&leaderboard, &filter);
+        parent.spawn(text).insert(LeaderboardText);
+        spawn_button(parent, &font_spec, "Back", ButtonAction::MainMenu);
+    });
+}
+
+fn spawn_settings_screen(mut commands: Commands, font_spec: Res<FontSpec>, settings: Res<Settings>) {
+    spawn_screen(&mut commands, &font_spec, "Settings", |parent| {
+        // options on the left, key bindings on the right, so the screen fits the window
+        parent.spawn(NodeBundle::default()).with_children(|parent| {
+            spawn_settings_column(parent, |parent| {
+                for field in SettingField::ALL {
+                    spawn_settings_row(parent, &font_spec, field.label(), |parent| {
+                        spawn_sized_button(parent, &font_spec, "<", ButtonAction::Adjust(field, -1), 30.0);
+                        let mut value = screen_text(&font_spec);
+                        value.text.sections[0].value = format!("{:^8}", settings.describe(field));
+                        parent.spawn(value).insert(SettingValue(field));
+                        spawn_sized_button(parent, &font_spec, ">", ButtonAction::Adjust(field, 1), 30.0);
                     });
-                    parent.spawn(TextBundle {
-                        text: Text::from_section(
-                            "<score>",
-                            TextStyle {
-                                font: font_spec.family.clone(),
-                                font_size: 20.0,
-                                color: Color::WHITE,
-                            }
-                        ).with_alignment(TextAlignment::Center),
-                        ..default()
-                    }).insert(ScoreDisplay);
+                }
+                spawn_settings_row(parent, &font_spec, "Board shape", |parent| {
+                    spawn_button(parent, &font_spec, "", ButtonAction::CycleShape);
                 });
-                parent.spawn(NodeBundle {
-                    style: Style {
-                        flex_direction: FlexDirection::Column,
-                        align_items: AlignItems::Center,
-                        padding: UiRect::all(Val::Px(10.0)),
-                        ..default()
-                    },
-                    background_color: NORMAL_BUTTON.into(),
-                    ..default()
-                }).with_children(|parent| {
-                    parent.spawn(TextBundle {
-                        text: Text::from_section(
-                          "Best",
-                            TextStyle {
-                                font: font_spec.family.clone(),
-                                font_size: 15.0,
-                                color: Color::WHITE,
-                            }
-                        ).with_alignment(TextAlignment::Center),
-                      ..default()
-                    });
-                    parent.spawn(TextBundle {
-                        text: Text::from_section(
-                            "<score>",
-                            TextStyle {
-                                font: font_spec.family.clone(),
-                                font_size: 20.0,
-                                color: Color::WHITE,
-                            }
-                        ).with_alignment(TextAlignment::Center),
-                       ..default()
-                    }).insert(BestScoreDisplay);
+                spawn_settings_row(parent, &font_spec, "Profile", |parent| {
+                    spawn_button(parent, &font_spec, "", ButtonAction::CycleProfile);
                 });
             });
-            parent.spawn(ButtonBundle {
+            spawn_settings_column(parent, |parent| {
+                for action in KeyAction::ALL {
+                    spawn_settings_row(parent, &font_spec, &format!("Key {action:?}"), |parent| {
+                        spawn_button(parent, &font_spec, "", ButtonAction::Rebind(action));
+                    });
+                }
+            });
+        });
+        spawn_button(parent, &font_spec, "Back", ButtonAction::MainMenu);
+    });
+}
+
+fn spawn_settings_column(parent: &mut ChildBuilder, content: impl FnOnce(&mut ChildBuilder)) {
+    parent.spawn(NodeBundle {
+        style: Style {
+            flex_direction: FlexDirection::Column,
+            margin: UiRect::horizontal(Val::Px(10.0)),
+            ..default()
+        },
+        ..default()
+    }).with_children(content);
+}
+
+fn spawn_settings_row(parent: &mut ChildBuilder, font_spec: &FontSpec, label: &str, content: impl FnOnce(&mut ChildBuilder)) {
+    parent.spawn(NodeBundle {
+        style: Style {
+            align_items: AlignItems::Center,
+            ..default()
+        },
+        ..default()
+    }).with_children(|parent| {
+        let mut text = screen_text(font_spec);
+        text.text.sections[0].value = format!("{label:<16}");
+        parent.spawn(text);
+        content(parent);
+    });
+}
+
+fn despawn_state_screens(mut commands: Commands, screens: Query<Entity, With<StateScreen>>) {
+    for entity in screens.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn spawn_panel(parent: &mut ChildBuilder, font_spec: &FontSpec, label: &str, display: impl Component) {
+    parent.spawn(NodeBundle {
+        style: Style {
+            flex_direction: FlexDirection::Column,
+            align_items: AlignItems::Center,
+            margin: UiRect::horizontal(Val::Px(10.0)),
+            padding: UiRect::all(Val::Px(10.0)),
+            ..default()
+        },
+        background_color: NORMAL_BUTTON.into(),
+        ..default()
+    }).with_children(|parent| {
+        parent.spawn(TextBundle {
+            text: Text::from_section(
+                label,
+                TextStyle {
+                    font: font_spec.family.clone(),
+                    font_size: 15.0,
+                    color: Color::WHITE,
+                }
+            ).with_alignment(TextAlignment::Center),
+            ..default()
+        });
+        parent.spawn(TextBundle {
+            text: Text::from_section(
+                "<score>",
+                TextStyle {
+                    font: font_spec.family.clone(),
+                    font_size: 20.0,
+                    color: Color::WHITE,
+                }
+            ).with_alignment(TextAlignment::Center),
+            ..default()
+        }).insert(display);
+    });
+}
+
+fn spawn_screen(commands: &mut Commands, font_spec: &FontSpec, title: &str, content: impl FnOnce(&mut ChildBuilder)) {
+    let screen = spawn_overlay(commands, |parent| {
+        parent.spawn(TextBundle {
+            text: Text::from_section(
+                title,
+                TextStyle {
+                    font: font_spec.family.clone(),
+                    font_size: 40.0,
+                    color: Color::WHITE,
+                }
+            ),
+            style: Style {
+                margin: UiRect::bottom(Val::Px(10.0)),
+                ..default()
+            },
+            ..default()
+        });
+        content(parent);
+    });
+    commands.entity(screen).insert(StateScreen);
+}
+
+// a panel centered above the board
+fn spawn_overlay(commands: &mut Commands, content: impl FnOnce(&mut ChildBuilder)) -> Entity {
+    commands.spawn(NodeBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..default()
+        },
+        ..default()
+    })
+        .with_children(|parent| {
+            parent.spawn(NodeBundle {
                 style: Style {
-                    size: Size::new(Val::Px(100.0), Val::Px(30.0)),
-                    justify_content: JustifyContent::Center,
+                    flex_direction: FlexDirection::Column,
                     align_items: AlignItems::Center,
+                    padding: UiRect::all(Val::Px(20.0)),
                     ..default()
                 },
+                background_color: SCREEN_BACKGROUND.into(),
                 ..default()
-            }).with_children(|parent| {
-                parent.spawn(TextBundle {
-                    text: Text::from_section(
-                        "Button",
-                        TextStyle {
-                            font: font_spec.family.clone(),
-                            font_size: 20.0,
-                            color: Color::rgb(0.9, 0.9, 0.9),
-                        },
-                    ),
-                   ..default()
-                });
-            });
+            }).with_children(content);
+        })
+        .id()
+}
+
+fn screen_text(font_spec: &FontSpec) -> TextBundle {
+    TextBundle {
+        text: Text::from_section(
+            "",
+            TextStyle {
+                font: font_spec.mono.clone(),
+                font_size: 18.0,
+                color: Color::WHITE,
+            }
+        ),
+        ..default()
+    }
+}
+
+fn spawn_button(parent: &mut ChildBuilder, font_spec: &FontSpec, label: &str, action: ButtonAction) {
+    spawn_sized_button(parent, font_spec, label, action, 100.0);
+}
+
+fn spawn_sized_button(parent: &mut ChildBuilder, font_spec: &FontSpec, label: &str, action: ButtonAction, width: f32) {
+    parent.spawn(ButtonBundle {
+        style: Style {
+            min_size: Size::new(Val::Px(width), Val::Px(30.0)),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            margin: UiRect::all(Val::Px(5.0)),
+            padding: UiRect::horizontal(Val::Px(10.0)),
+            ..default()
+        },
+        ..default()
+    }).with_children(|parent| {
+        parent.spawn(TextBundle {
+            text: Text::from_section(
+                label,
+                TextStyle {
+                    font: font_spec.family.clone(),
+                    font_size: 20.0,
+                    color: Color::rgb(0.9, 0.9, 0.9),
+                },
+            ),
+           ..default()
         });
+    }).insert(action);
 }
 
 //part 17
 
+// with more than one board, the scores of all of them from left to right
 fn scoreboard(
     game: Res<Game>,
-    mut query_score: ParamSet<(Query<&mut Text, With<ScoreDisplay>>, Query<&mut Text, With<BestScoreDisplay>>)>) {
+    boards: Query<(&BoardIndex, &Progress)>,
+    mut query_score: ParamSet<(
+        Query<&mut Text, With<ScoreDisplay>>,
+        Query<&mut Text, With<BestScoreDisplay>>,
+        Query<&mut Text, With<MovesDisplay>>,
+        Query<&mut Text, With<TimeDisplay>>,
+    )>) {
 
-    query_score.p0().single_mut().sections[0].value = game.score.to_string();
+    let boards: Vec<&Progress> = boards.iter().sorted_by_key(|(index, _)| **index).map(|(_, progress)| progress).collect();
+    query_score.p0().single_mut().sections[0].value = boards.iter().map(|progress| progress.score).join(" - ");
     query_score.p1().single_mut().sections[0].value = game.score_best.to_string();
+    // the limited modes count down instead
+    query_score.p2().single_mut().sections[0].value = boards.iter().map(|progress| match game.moves_left(progress) {
+        Some(left) => format!("{left} left"),
+        None => progress.moves.to_string(),
+    }).join(" - ");
+    query_score.p3().single_mut().sections[0].value = match game.time_left() {
+        Some(left) => format!("{} left", format_duration(left)),
+        None => format_duration(game.elapsed),
+    };
+}
+
+// "Bot: name" under the title while a bot plays, with why it stopped if it did
+fn bot_display(player: Option<Res<BotPlayer>>, mut query: Query<&mut Text, With<BotDisplay>>) {
+    let label = player.map_or(String::new(), |player| format!("\n{}", player.label()));
+    for mut text in query.iter_mut() {
+        if text.sections[1].value != label { text.sections[1].value = label.clone(); }
+    }
 }
 
 // part 20
 const NORMAL_BUTTON: Color = Color::rgb(0.75, 0.75, 0.9);
 const HOVERED_BUTTON: Color = Color::rgb(0.7, 0.7, 0.9);
 const PRESSED_BUTTON: Color = Color::rgb(0.6, 0.6, 0.95);
+const SCREEN_BACKGROUND: Color = Color::rgba(0.15, 0.15, 0.25, 0.95);
 
 fn button_interaction_system(
     mut interaction_query: Query<
-        (&Interaction, &mut BackgroundColor),
+        (&Interaction, &mut BackgroundColor, &ButtonAction),
         (Changed<Interaction>, With<Button>)
     >,
-    run_state: Res<State<RunState>>,
+    mut filter: ResMut<LeaderboardFilter>,
+    leaderboard: Res<Leaderboard>,
+    shapes: Res<Shapes>,
+    mut settings: ResMut<Settings>,
+    mut rebinding: ResMut<Rebinding>,
+    mut game: ResMut<Game>,
+    daily: Res<DailyRecords>,
+    storage: Res<Storage>,
+    mut profile: ResMut<Profile>,
     mut next_state: ResMut<NextState<RunState>>,
+    mut exit: EventWriter<bevy::app::AppExit>,
 ) {
-    for (interaction, mut background_color) in interaction_query.iter_mut() {
+    for (interaction, mut background_color, action) in interaction_query.iter_mut() {
         match *interaction {
             Interaction::Clicked => {
                 *background_color = PRESSED_BUTTON.into();
-                match run_state.0 {
-                    RunState::Playing => { next_state.set(RunState::GameOver); }
-                    RunState::GameOver => { next_state.set(RunState::Playing); }
+                match action {
+                    ButtonAction::Play => { next_state.set(RunState::Playing); }
+                    ButtonAction::Start(mode) => {
+                        game.mode = *mode;
+                        game.in_progress = false;
+                        next_state.set(RunState::Playing);
+                    }
+                    ButtonAction::Puzzles => { next_state.set(RunState::Puzzles); }
+                    ButtonAction::Daily => { next_state.set(RunState::Daily); }
+                    ButtonAction::Online => { next_state.set(RunState::Lobby); }
+                    ButtonAction::ExportDaily(day) => { export_daily(*day, &daily, &storage, &profile); }
+                    ButtonAction::EndGame => { next_state.set(RunState::GameOver); }
+                    ButtonAction::MainMenu => { next_state.set(RunState::MainMenu); }
+                    ButtonAction::Statistics => { next_state.set(RunState::Statistics); }
+                    ButtonAction::Leaderboard => { next_state.set(RunState::Leaderboard); }
+                    ButtonAction::Settings => { next_state.set(RunState::Settings); }
+                    ButtonAction::Quit => { exit.send(bevy::app::AppExit); }
+                    ButtonAction::CycleSizeFilter => {
+                        let sizes = leaderboard.entries.iter().map(|entry| entry.board()).sorted().dedup();
+                        filter.board = next_filter(sizes.collect(), &filter.board);
+                    }
+                    ButtonAction::CycleVariantFilter => {
+                        let variants = leaderboard.entries.iter().map(|entry| entry.variant.clone()).sorted().dedup();
+                        filter.variant = next_filter(variants.collect(), &filter.variant);
+                    }
+                    ButtonAction::CycleShape => {
+                        let names = shapes.0.iter().map(|shape| shape.name.clone()).collect();
+                        settings.shape = next_filter(names, &settings.shape);
+                    }
+                    ButtonAction::CycleProfile => {
+                        let names = profile_names(&leaderboard, &profile.name);
+                        let next = next_filter(names.clone(), &Some(profile.name.clone())).unwrap_or_else(|| names[0].clone());
+                        profile.set_if_neq(Profile { name: next });
+                    }
+                    ButtonAction::Adjust(field, step) => { settings.adjust(*field, *step); }
+                    ButtonAction::Rebind(action) => { rebinding.0 = Some(*action); }
                 }
             }
             Interaction::Hovered => { *background_color = HOVERED_BUTTON.into(); }
@@ -186,15 +691,342 @@ fn button_interaction_system(
     }
 }
 
+// all, then every option in turn, then all again
+fn next_filter<T: PartialEq>(options: Vec<T>, current: &Option<T>) -> Option<T> {
+    let mut options = options.into_iter();
+    match current {
+        None => options.next(),
+        Some(current) => options.skip_while(|option| option != current).nth(1),
+    }
+}
+
+// everyone who typed a name on the leaderboard has a profile, and so does the default player
+fn profile_names(leaderboard: &Leaderboard, current: &str) -> Vec<String> {
+    let default = Profile::default().name;
+    leaderboard.entries.iter().map(|entry| entry.name.as_str())
+        .chain([current, default.as_str()])
+        .map(String::from)
+        .sorted()
+        .dedup()
+        .collect()
+}
+
 fn button_text_system(
-    button_query: Query<&Children, With<Button>>,
+    button_query: Query<(&Children, &ButtonAction), With<Button>>,
     mut text_query: Query<&mut Text>,
-    run_state: Res<State<RunState>>,
+    filter: Res<LeaderboardFilter>,
+    settings: Res<Settings>,
+    rebinding: Res<Rebinding>,
+    profile: Res<Profile>,
+) {
+    for (children, action) in button_query.iter() {
+        let label = match action {
+            ButtonAction::CycleSizeFilter => match filter.board {
+                None => "All sizes".to_string(),
+                Some((width, height)) => format!("{width}x{height}"),
+            },
+            ButtonAction::CycleVariantFilter => filter.variant.clone().unwrap_or("All variants".to_string()),
+            ButtonAction::CycleShape => settings.shape.clone().unwrap_or("Rectangle".to_string()),
+            ButtonAction::CycleProfile => profile.name.clone(),
+            ButtonAction::Rebind(action) if rebinding.0 == Some(*action) => "press a key".to_string(),
+            ButtonAction::Rebind(action) => format!("{:?}", settings.key_bindings.key(*action)),
+            _ => continue,
+        };
+        let mut text = text_query.get_mut(*children.first().expect("button is to have only one child")).unwrap();
+        if text.sections[0].value != label {
+            text.sections[0].value = label;
+        }
+    }
+}
+
+fn statistics_lines(statistics: &Statistics) -> String {
+    let mut lines = vec![
+        format!("Games played   {}", statistics.games_played),
+        format!("Wins           {}", statistics.wins),
+        format!("Average score  {}", statistics.average_score()),
+        format!("Median score   {}", statistics.median_score()),
+        format!("Total moves    {}", statistics.total_moves),
+        format!("Time played    {}", format_duration(statistics.time_played)),
+        format!("Longest streak {}", statistics.longest_streak),
+        String::new(),
+        "Best tiles".to_string(),
+    ];
+    let most_games = statistics.best_tiles.values().copied().max().unwrap_or(0);
+    for (tile, games) in statistics.best_tiles.iter().rev() {
+        let bar = "#".repeat((games * 20).div_ceil(most_games) as usize);
+        lines.push(format!("{tile:>6} {bar:<20} {games}"));
+    }
+
+    lines.join("\n")
+}
+
+fn daily_lines(records: &DailyRecords, day: i64) -> String {
+    let mut lines = match records.get(day) {
+        None => "One try, the same tiles for everyone today\n".to_string(),
+        Some(result) if result.finished => format!("Today {} with a {} in {} moves\n", result.score, result.best_tile, result.moves),
+        Some(_) => "Today's try was left unfinished\n".to_string(),
+    };
+    if let Some((best_day, best)) = records.best().filter(|(_, best)| best.finished) {
+        lines += &format!("Best daily {} on {best_day}\n", best.score);
+    }
+    lines
+}
+
+// the winner of a race is whoever got to the target first, or else has the higher score.
+// every board comes with its name and whether it is the board of the player at the screen
+fn result_line(boards: Vec<(&str, bool, &Progress)>) -> String {
+    match boards.as_slice() {
+        [(_, _, progress)] => format!("Score {} in {} moves", progress.score, progress.moves),
+        _ => {
+            let scores = boards.iter().map(|(_, _, progress)| progress.score).join(" to ");
+            let ranked: Vec<_> = boards.iter().sorted_by_key(|(_, _, progress)| (progress.won, progress.score)).rev().collect();
+            let rank = |index: usize| ranked.get(index).map(|(_, _, progress)| (progress.won, progress.score));
+            match ranked.first() {
+                Some((_, true, _)) if rank(0) != rank(1) => format!("You win, {scores}"),
+                Some((name, _, _)) if rank(0) != rank(1) => format!("{name} wins, {scores}"),
+                _ => format!("A draw, {scores}"),
+            }
+        }
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    match seconds {
+        0..=3599 => format!("{}:{:02}", seconds / 60, seconds % 60),
+        _ => format!("{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60),
+    }
+}
+
+fn leaderboard_text(
+    leaderboard: Res<Leaderboard>,
+    filter: Res<LeaderboardFilter>,
+    mut text_query: Query<&mut Text, With<LeaderboardText>>,
+) {
+    if !leaderboard.is_changed() && !filter.is_changed() { return; }
+    if let Ok(mut text) = text_query.get_single_mut() {
+        text.sections[0].value = leaderboard_lines(&leaderboard, &filter);
+    }
+}
+
+fn leaderboard_lines(leaderboard: &Leaderboard, filter: &LeaderboardFilter) -> String {
+    let mut lines = vec![
+        format!(" # {:<12} {:>7} {:>5} {:>4} {:<8} {:>5} {:>7} {:<10}", "Name", "Score", "Tile", "Size", "Variant", "Moves", "Time", "Date"),
+    ];
+    let entries = leaderboard.filtered(filter.board, filter.variant.as_deref());
+    for (rank, entry) in entries.take(crate::leaderboard::LEADERBOARD_SIZE).enumerate() {
+        lines.push(format!(
+            "{:>2} {:<12} {:>7} {:>5} {:>4} {:<8} {:>5} {:>7} {:<10}",
+            rank + 1,
+            entry.name,
+            entry.score,
+            entry.max_tile,
+            format!("{}x{}", entry.board_width, entry.board_height),
+            entry.variant,
+            entry.moves,
+            format_duration(entry.duration),
+            entry.date,
+        ));
+    }
+
+    lines.join("\n")
+}
+
+fn name_entry(
+    mut characters: EventReader<ReceivedCharacter>,
+    keys: Res<Input<KeyCode>>,
+    mut pending: ResMut<PendingEntry>,
+    mut leaderboard: ResMut<Leaderboard>,
+    storage: Res<Storage>,
+    mut screen: Query<&mut Visibility, With<NameEntryScreen>>,
+    mut text_query: Query<&mut Text, With<NameEntryText>>,
+    mut next_state: ResMut<NextState<RunState>>,
+    mut edited: Local<bool>,
+) {
+    screen.single_mut().set_if_neq(if pending.0.is_some() { Visibility::Inherited } else { Visibility::Hidden });
+    let Some(entry) = pending.0.as_mut() else {
+        characters.clear();
+        *edited = false;
+        return;
+    };
+
+    // the profile name is there to take with enter, typing something else replaces it
+    for character in characters.iter().map(|event| event.char).filter(|char| !char.is_control()) {
+        if !*edited { entry.name.clear(); }
+        *edited = true;
+        if entry.name.chars().count() < MAX_NAME_LENGTH {
+            entry.name.push(character);
+        }
+    }
+    if keys.just_pressed(KeyCode::Back) {
+        *edited = true;
+        entry.name.pop();
+    }
+    text_query.single_mut().sections[0].value = format!("{}_", entry.name);
+
+    if keys.just_pressed(KeyCode::Return) && !entry.name.trim().is_empty() {
+        submit_high_score(&mut pending, &mut leaderboard, &storage);
+        next_state.set(RunState::Leaderboard);
+    }
+}
+
+// grows and shrinks the ui with the window height. bevy already multiplies this by the
+// window scale factor, so on a high-dpi screen text gets sharper rather than bigger
+fn scale_ui(windows: Query<&Window, With<PrimaryWindow>>, mut ui_scale: ResMut<UiScale>) {
+    let Ok(window) = windows.get_single() else { return; };
+    let scale = f64::from((window.height() / REFERENCE_HEIGHT).clamp(0.75, 2.0));
+    if ui_scale.scale != scale {
+        ui_scale.scale = scale;
+    }
+}
+
+// zooms the camera so the boards fill the space left below the hud, and moves it so they
+// sit in the middle of that space instead of behind the score panels
+fn fit_board_to_window(
+    windows: Query<&Window, With<PrimaryWindow>>,
+    hud: Query<&Node, With<Hud>>,
+    boards: Query<(&Board, &Transform), Without<Camera2d>>,
+    mut cameras: Query<(&mut OrthographicProjection, &mut Transform), With<Camera2d>>,
+) {
+    let Ok(window) = windows.get_single() else { return; };
+    // around all boards, with room for the labels of side by side boards
+    let Some((min, max)) = boards.iter()
+        .map(|(board, transform)| {
+            let center = transform.translation.truncate();
+            (center - board.physical_size / 2.0, center + board.physical_size / 2.0)
+        })
+        .reduce(|(min_a, max_a), (min_b, max_b)| (min_a.min(min_b), max_a.max(max_b))) else { return; };
+    let labels = if boards.iter().len() > 1 { Vec2::new(0.0, 2.0 * TILE_SIZE) } else { Vec2::ZERO };
+    let hud_height = hud.get_single().map_or(0.0, |node| node.size().y);
+    let free = Vec2::new(window.width(), (window.height() - hud_height).max(1.0)) * BOARD_MARGIN;
+    let scale = ((max - min + labels) / free).max_element();
+
+    for (mut projection, mut transform) in cameras.iter_mut() {
+        if projection.scale != scale {
+            projection.scale = scale;
+        }
+        let y = hud_height / 2.0 * scale;
+        if transform.translation.y != y {
+            transform.translation.y = y;
+        }
+    }
+}
+
+fn spawn_score_popups(
+    mut commands: Commands,
+    mut gain_reader: EventReader<ScoreGainEvent>,
+    score_panel: Query<&Parent, With<ScoreDisplay>>,
+    boards: Query<&Board>,
+    font_spec: Res<FontSpec>,
+) {
+    for gain in gain_reader.iter() {
+        let start = Style {
+            position_type: PositionType::Absolute,
+            position: UiRect { top: Val::Px(0.0), ..default() },
+            ..default()
+        };
+        let end = Style {
+            position: UiRect { top: Val::Px(-POPUP_RISE), ..default() },
+            ..start
+        };
+        let popup = commands.spawn(TextBundle {
+            text: Text::from_section(
+                format!("+{}", gain.total),
+                TextStyle {
+                    font: font_spec.family.clone(),
+                    font_size: 20.0,
+                    color: Color::WHITE,
+                }
+            ),
+            style: start.clone(),
+            ..default()
+        })
+            .insert(start.ease_to(end, EaseFunction::QuadraticOut, EasingType::Once { duration: POPUP_DURATION }))
+            .insert(Popup::default())
+            .id();
+        commands.entity(score_panel.single().get()).add_child(popup);
+
+        // over the merged tiles, on their board
+        let Ok(board) = boards.get(gain.board) else { continue; };
+        for (position, value) in gain.merges.iter() {
+            let start = Transform::from_translation(board.cell_position_to_physical(*position).extend(3.0));
+            let popup = commands.spawn(Text2dBundle {
+                text: Text::from_section(
+                    format!("+{value}"),
+                    TextStyle {
+                        font: font_spec.family.clone(),
+                        font_size: 20.0,
+                        color: Color::ORANGE_RED,
+                    }
+                ).with_alignment(TextAlignment::Center),
+                transform: start,
+                ..default()
+            })
+                .insert(start.ease_to(
+                    start.with_translation(start.translation + Vec3::Y * POPUP_RISE),
+                    EaseFunction::QuadraticOut,
+                    EasingType::Once { duration: POPUP_DURATION },
+                ))
+                .insert(Popup::default())
+                .id();
+            commands.entity(gain.board).add_child(popup);
+        }
+    }
+}
+
+fn fade_popups(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut popups: Query<(Entity, &mut Popup, &mut Text)>,
 ) {
-    let children = button_query.single();
-    let mut text = text_query.get_mut(*children.first().expect("button is to have only one child")).unwrap();
-    match run_state.0 {
-        RunState::Playing => { text.sections[0].value = "End Game".to_string(); }
-        RunState::GameOver => { text.sections[0].value = "New Game".to_string(); }
+    for (entity, mut popup, mut text) in popups.iter_mut() {
+        if popup.timer.tick(time.delta()).finished() {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+        for section in text.sections.iter_mut() {
+            section.style.color.set_a(popup.timer.percent_left());
+        }
+    }
+}
+
+fn settings_text(
+    settings: Res<Settings>,
+    mut values: Query<(&mut Text, &SettingValue)>,
+) {
+    if !settings.is_changed() { return; }
+    for (mut text, SettingValue(field)) in values.iter_mut() {
+        text.sections[0].value = format!("{:^8}", settings.describe(*field));
+    }
+}
+
+fn rebind_key(
+    keys: Res<Input<KeyCode>>,
+    mut rebinding: ResMut<Rebinding>,
+    mut settings: ResMut<Settings>,
+) {
+    let Some(action) = rebinding.0 else { return; };
+    // escape leaves the settings screen, it cannot be bound
+    let Some(key) = keys.get_just_pressed().find(|key| **key != KeyCode::Escape) else { return; };
+
+    *settings.key_bindings.key_mut(action) = *key;
+    rebinding.0 = None;
+}
+
+fn stop_rebinding(mut rebinding: ResMut<Rebinding>) {
+    rebinding.0 = None;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn result_lines_name_the_winner() {
+        let (low, high) = (Progress { score: 8, ..default() }, Progress { score: 16, ..default() });
+        assert_eq!(result_line(vec![("Me", true, &high), ("Opponent", false, &low)]), "You win, 16 to 8");
+        assert_eq!(result_line(vec![("Me", true, &low), ("Opponent", false, &high)]), "Opponent wins, 8 to 16");
+        assert_eq!(result_line(vec![("Player 1", false, &high), ("Player 2", false, &low)]), "Player 1 wins, 16 to 8");
+        assert_eq!(result_line(vec![("Player 1", false, &low), ("Player 2", false, &low)]), "A draw, 8 to 8");
     }
 }